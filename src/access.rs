@@ -0,0 +1,305 @@
+use std::{
+    ffi::{CStr, CString},
+    fs,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::Path,
+};
+
+use crate::{
+    acl::{self, Acl, Tag},
+    lookup_with_buffer,
+};
+
+/// A user whose access is computed from metadata instead of asked from the
+/// kernel.
+#[derive(Debug, Clone)]
+pub struct Creds {
+    pub uid: u32,
+    pub gid: u32,
+    pub groups: Vec<u32>,
+}
+
+/// Name, uid and primary gid of the user `name`, which may also be a uid.
+fn get_passwd(name: &str) -> Result<Option<(CString, u32, u32)>, String> {
+    let cname = CString::new(name).map_err(|e| e.to_string())?;
+    let uid: Option<u32> = name.parse().ok();
+    let mut ids = None;
+    lookup_with_buffer(|buf| {
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut res: *mut libc::passwd = std::ptr::null_mut();
+        let (buf, len) = (buf.as_mut_ptr() as *mut _, buf.len());
+        let ret = unsafe {
+            match uid {
+                Some(uid) => libc::getpwuid_r(uid, &mut passwd, buf, len, &mut res),
+                None => libc::getpwnam_r(cname.as_ptr(), &mut passwd, buf, len, &mut res),
+            }
+        };
+        if ret == 0 && !res.is_null() {
+            let pw_name = unsafe { CStr::from_ptr(passwd.pw_name) }.to_owned();
+            ids = Some((pw_name, passwd.pw_uid, passwd.pw_gid));
+        }
+        ret
+    })
+    .map_err(|e| format!("cannot look up user `{name}`: {e}"))?;
+    Ok(ids)
+}
+
+/// Resolves a user name (or uid) with its primary and supplementary groups.
+pub fn parse_user(name: &str) -> Result<Creds, String> {
+    let (cname, uid, gid) = get_passwd(name)?.ok_or(format!("no such user `{name}`"))?;
+
+    let mut groups: Vec<libc::gid_t> = vec![0; 64];
+    loop {
+        let mut count = groups.len() as libc::c_int;
+        let ret =
+            unsafe { libc::getgrouplist(cname.as_ptr(), gid, groups.as_mut_ptr(), &mut count) };
+        if ret >= 0 {
+            groups.truncate(count as usize);
+            break;
+        }
+        groups.resize((count as usize).max(groups.len() * 2), 0);
+    }
+    Ok(Creds { uid, gid, groups })
+}
+
+/// Effective `rwx` of the invoking user, asked from the kernel, so root,
+/// ACLs, read-only mounts and immutable files are all accounted for.
+pub fn get_effective_access(path: &Path) -> String {
+    let Ok(cpath) = CString::new(path.as_os_str().as_bytes()) else {
+        return "???".to_string();
+    };
+    [(libc::R_OK, 'r'), (libc::W_OK, 'w'), (libc::X_OK, 'x')]
+        .iter()
+        .map(|&(how, c)| {
+            let ret =
+                unsafe { libc::faccessat(libc::AT_FDCWD, cpath.as_ptr(), how, libc::AT_EACCESS) };
+            if ret == 0 { c } else { '-' }
+        })
+        .collect()
+}
+
+/// Metadata of the entry whose access is being computed.
+pub struct Target<'a> {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub is_dir: bool,
+    pub acl: Option<&'a Acl>,
+}
+
+/// Permission bits (`0o7` scale) `creds` gets on `target`, following the
+/// POSIX ACL access check: owner, then named users, then every matching
+/// group entry, then others, with the mask limiting all but owner and other.
+fn get_perm(creds: &Creds, target: &Target) -> u32 {
+    let in_group = |gid: u32| creds.gid == gid || creds.groups.contains(&gid);
+    let mode = target.mode;
+
+    if creds.uid == 0 {
+        // root reads and writes anything, and executes what anyone may
+        let x = target.is_dir || mode & 0o111 != 0;
+        return 0o6 | if x { 0o1 } else { 0 };
+    }
+    if creds.uid == target.uid {
+        return (mode >> 6) & 0o7;
+    }
+
+    let Some(acl) = target.acl else {
+        return if in_group(target.gid) {
+            (mode >> 3) & 0o7
+        } else {
+            mode & 0o7
+        };
+    };
+
+    let mask = acl
+        .entries
+        .iter()
+        .find(|e| e.tag == Tag::Mask)
+        .map_or(0o7, |e| e.perm as u32);
+    if let Some(e) = acl.entries.iter().find(|e| e.tag == Tag::User(creds.uid)) {
+        return e.perm as u32 & mask;
+    }
+    let groups: Vec<u32> = acl
+        .entries
+        .iter()
+        .filter(|e| match e.tag {
+            Tag::GroupObj => in_group(target.gid),
+            Tag::Group(gid) => in_group(gid),
+            _ => false,
+        })
+        .map(|e| e.perm as u32)
+        .collect();
+    if !groups.is_empty() {
+        // each bit is checked on its own, so the union of the matching
+        // entries is what the kernel would grant
+        return groups.iter().fold(0, |acc, p| acc | p) & mask;
+    }
+    mode & 0o7
+}
+
+/// `rwx` that `creds` gets on `target`, from the mode bits and ACL alone.
+pub fn get_access_for(creds: &Creds, target: &Target) -> String {
+    let perm = get_perm(creds, target);
+    [(4, 'r'), (2, 'w'), (1, 'x')]
+        .iter()
+        .map(|&(bit, c)| if perm & bit != 0 { c } else { '-' })
+        .collect()
+}
+
+/// Like `get_access_for`, for whatever the symlink `path` resolves to, since
+/// the permission bits of a symlink itself are never checked.
+pub fn get_access_through_link(creds: &Creds, path: &Path) -> String {
+    let Ok(target) = fs::canonicalize(path) else {
+        return "---".to_string();
+    };
+    let Ok(md) = fs::metadata(&target) else {
+        return "---".to_string();
+    };
+    let acl = Acl::read(&target, acl::ACCESS).ok().flatten();
+    get_access_for(
+        creds,
+        &Target {
+            mode: md.mode(),
+            uid: md.uid(),
+            gid: md.gid(),
+            is_dir: md.is_dir(),
+            acl: acl.as_ref(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::Entry;
+
+    fn creds(uid: u32, gid: u32, groups: &[u32]) -> Creds {
+        Creds {
+            uid,
+            gid,
+            groups: groups.to_vec(),
+        }
+    }
+
+    fn acl(entries: &[(Tag, u16)]) -> Acl {
+        Acl {
+            entries: entries
+                .iter()
+                .map(|&(tag, perm)| Entry { tag, perm })
+                .collect(),
+        }
+    }
+
+    fn access(creds: &Creds, mode: u32, acl: Option<&Acl>) -> String {
+        let target = Target {
+            mode,
+            uid: 1000,
+            gid: 100,
+            is_dir: false,
+            acl,
+        };
+        get_access_for(creds, &target)
+    }
+
+    #[test]
+    fn mode_bits() {
+        assert_eq!(access(&creds(1000, 1000, &[]), 0o640, None), "rw-");
+        assert_eq!(access(&creds(2000, 100, &[]), 0o640, None), "r--");
+        assert_eq!(access(&creds(2000, 2000, &[100]), 0o640, None), "r--");
+        assert_eq!(access(&creds(2000, 2000, &[]), 0o644, None), "r--");
+        // the owner gets the owner bits even when others get more
+        assert_eq!(access(&creds(1000, 1000, &[]), 0o077, None), "---");
+    }
+
+    #[test]
+    fn root() {
+        let root = creds(0, 0, &[]);
+        assert_eq!(access(&root, 0o000, None), "rw-");
+        assert_eq!(access(&root, 0o644, None), "rw-");
+        assert_eq!(access(&root, 0o744, None), "rwx");
+        assert_eq!(access(&root, 0o001, None), "rwx");
+        let dir = Target {
+            mode: 0o000,
+            uid: 1000,
+            gid: 100,
+            is_dir: true,
+            acl: None,
+        };
+        assert_eq!(get_access_for(&root, &dir), "rwx");
+    }
+
+    #[test]
+    fn acl_owner_uses_owner_bits() {
+        let acl = acl(&[
+            (Tag::UserObj, 6),
+            (Tag::User(1000), 7),
+            (Tag::GroupObj, 4),
+            (Tag::Mask, 7),
+            (Tag::Other, 0),
+        ]);
+        assert_eq!(access(&creds(1000, 1000, &[]), 0o670, Some(&acl)), "rw-");
+    }
+
+    #[test]
+    fn acl_named_user() {
+        let with_mask = acl(&[
+            (Tag::UserObj, 6),
+            (Tag::User(2000), 7),
+            (Tag::GroupObj, 4),
+            (Tag::Mask, 5),
+            (Tag::Other, 0),
+        ]);
+        assert_eq!(
+            access(&creds(2000, 2000, &[]), 0o650, Some(&with_mask)),
+            "r-x"
+        );
+
+        let without_mask = acl(&[
+            (Tag::UserObj, 6),
+            (Tag::User(2000), 6),
+            (Tag::GroupObj, 4),
+            (Tag::Other, 0),
+        ]);
+        assert_eq!(
+            access(&creds(2000, 2000, &[]), 0o640, Some(&without_mask)),
+            "rw-"
+        );
+        // a named user entry wins over a matching group entry
+        assert_eq!(
+            access(&creds(2000, 100, &[]), 0o640, Some(&without_mask)),
+            "rw-"
+        );
+    }
+
+    #[test]
+    fn acl_groups_union_then_mask() {
+        let acl = acl(&[
+            (Tag::UserObj, 6),
+            (Tag::GroupObj, 4),
+            (Tag::Group(200), 2),
+            (Tag::Group(300), 1),
+            (Tag::Mask, 6),
+            (Tag::Other, 0),
+        ]);
+        assert_eq!(
+            access(&creds(2000, 100, &[200, 300]), 0o660, Some(&acl)),
+            "rw-"
+        );
+        assert_eq!(access(&creds(2000, 300, &[]), 0o660, Some(&acl)), "---");
+        assert_eq!(access(&creds(2000, 2000, &[200]), 0o660, Some(&acl)), "-w-");
+    }
+
+    #[test]
+    fn acl_non_member_falls_through_to_other() {
+        let acl = acl(&[
+            (Tag::UserObj, 6),
+            (Tag::User(3000), 7),
+            (Tag::GroupObj, 6),
+            (Tag::Group(200), 6),
+            (Tag::Mask, 6),
+            (Tag::Other, 4),
+        ]);
+        // other is not limited by the mask
+        assert_eq!(access(&creds(2000, 2000, &[]), 0o665, Some(&acl)), "r-x");
+    }
+}
@@ -0,0 +1,226 @@
+use std::{io, path::Path};
+
+use crate::xattr;
+
+pub const ACCESS: &str = "system.posix_acl_access";
+pub const DEFAULT: &str = "system.posix_acl_default";
+
+const VERSION: u32 = 2;
+const USER_OBJ: u16 = 0x01;
+const USER: u16 = 0x02;
+const GROUP_OBJ: u16 = 0x04;
+const GROUP: u16 = 0x08;
+const MASK: u16 = 0x10;
+const OTHER: u16 = 0x20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    UserObj,
+    User(u32),
+    GroupObj,
+    Group(u32),
+    Mask,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub tag: Tag,
+    pub perm: u16,
+}
+
+/// A POSIX ACL as the kernel stores it in `system.posix_acl_*`: a
+/// little-endian version word followed by 8-byte (tag, perm, id) entries.
+#[derive(Debug)]
+pub struct Acl {
+    pub entries: Vec<Entry>,
+}
+
+fn le16(b: &[u8]) -> u16 {
+    u16::from_le_bytes([b[0], b[1]])
+}
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn get_perm_str(perm: u16) -> String {
+    [(4, 'r'), (2, 'w'), (1, 'x')]
+        .iter()
+        .map(|&(bit, c)| if perm & bit != 0 { c } else { '-' })
+        .collect()
+}
+
+impl Acl {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 4 || le32(bytes) != VERSION || !(bytes.len() - 4).is_multiple_of(8) {
+            return None;
+        }
+        let entries = bytes[4..]
+            .chunks_exact(8)
+            .map(|e| {
+                let id = le32(&e[4..]);
+                let tag = match le16(e) {
+                    USER_OBJ => Tag::UserObj,
+                    USER => Tag::User(id),
+                    GROUP_OBJ => Tag::GroupObj,
+                    GROUP => Tag::Group(id),
+                    MASK => Tag::Mask,
+                    OTHER => Tag::Other,
+                    _ => return None,
+                };
+                Some(Entry {
+                    tag,
+                    perm: le16(&e[2..]),
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { entries })
+    }
+
+    /// Reads the ACL stored in the xattr `name` (`ACCESS` or `DEFAULT`) of
+    /// `path`. Entries without one, and filesystems without ACL support,
+    /// give `None`.
+    pub fn read(path: &Path, name: &str) -> io::Result<Option<Self>> {
+        match xattr::get(path, name) {
+            Ok(bytes) => Ok(Self::parse(&bytes)),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ENODATA | libc::ENOTSUP)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the ACL says more than the permission bits already do, which
+    /// is when `ls` shows its `+`.
+    pub fn is_extended(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|e| !matches!(e.tag, Tag::UserObj | Tag::GroupObj | Tag::Other))
+    }
+
+    fn mask(&self) -> Option<u16> {
+        self.entries
+            .iter()
+            .find(|e| e.tag == Tag::Mask)
+            .map(|e| e.perm)
+    }
+
+    /// The entries in `getfacl` form (`user:alice:rw-`), with an
+    /// `#effective:` note where the mask takes permissions away. `prefix` is
+    /// prepended to every line, e.g. `default:`.
+    pub fn to_lines(
+        &self,
+        prefix: &str,
+        user: impl Fn(u32) -> String,
+        group: impl Fn(u32) -> String,
+    ) -> Vec<String> {
+        let mask = self.mask();
+        self.entries
+            .iter()
+            .map(|e| {
+                let (kind, qualifier, masked) = match e.tag {
+                    Tag::UserObj => ("user", String::new(), false),
+                    Tag::User(uid) => ("user", user(uid), true),
+                    Tag::GroupObj => ("group", String::new(), true),
+                    Tag::Group(gid) => ("group", group(gid), true),
+                    Tag::Mask => ("mask", String::new(), false),
+                    Tag::Other => ("other", String::new(), false),
+                };
+                let mut line = format!("{prefix}{kind}:{qualifier}:{}", get_perm_str(e.perm));
+                if let Some(mask) = mask
+                    && masked
+                    && e.perm & !mask & 0o7 != 0
+                {
+                    line.push_str(&format!("\t#effective:{}", get_perm_str(e.perm & mask)));
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(entries: &[(u16, u16, u32)]) -> Vec<u8> {
+        let mut bytes = VERSION.to_le_bytes().to_vec();
+        for &(tag, perm, id) in entries {
+            bytes.extend(tag.to_le_bytes());
+            bytes.extend(perm.to_le_bytes());
+            bytes.extend(id.to_le_bytes());
+        }
+        bytes
+    }
+
+    const UNDEFINED: u32 = u32::MAX;
+
+    fn extended() -> Vec<u8> {
+        encode(&[
+            (USER_OBJ, 6, UNDEFINED),
+            (USER, 7, 1000),
+            (GROUP_OBJ, 4, UNDEFINED),
+            (GROUP, 6, 100),
+            (MASK, 5, UNDEFINED),
+            (OTHER, 0, UNDEFINED),
+        ])
+    }
+
+    #[test]
+    fn parses_tags() {
+        let acl = Acl::parse(&extended()).unwrap();
+        let tags: Vec<Tag> = acl.entries.iter().map(|e| e.tag).collect();
+        assert_eq!(
+            tags,
+            [
+                Tag::UserObj,
+                Tag::User(1000),
+                Tag::GroupObj,
+                Tag::Group(100),
+                Tag::Mask,
+                Tag::Other
+            ]
+        );
+        assert_eq!(acl.entries[1].perm, 7);
+        assert!(acl.is_extended());
+    }
+
+    #[test]
+    fn minimal_acl_is_not_extended() {
+        let acl = Acl::parse(&encode(&[
+            (USER_OBJ, 6, UNDEFINED),
+            (GROUP_OBJ, 4, UNDEFINED),
+            (OTHER, 4, UNDEFINED),
+        ]))
+        .unwrap();
+        assert!(!acl.is_extended());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let good = extended();
+        assert!(Acl::parse(&[]).is_none());
+        assert!(Acl::parse(&good[..good.len() - 1]).is_none());
+        assert!(Acl::parse(&encode(&[(0x40, 7, 0)])).is_none());
+        let mut version = good.clone();
+        version[0] = 1;
+        assert!(Acl::parse(&version).is_none());
+    }
+
+    #[test]
+    fn getfacl_lines() {
+        let acl = Acl::parse(&extended()).unwrap();
+        let lines = acl.to_lines("", |uid| format!("u{uid}"), |gid| format!("g{gid}"));
+        assert_eq!(
+            lines,
+            [
+                "user::rw-",
+                "user:u1000:rwx\t#effective:r-x",
+                "group::r--",
+                "group:g100:rw-\t#effective:r--",
+                "mask::r-x",
+                "other::---",
+            ]
+        );
+        let lines = acl.to_lines("default:", |uid| uid.to_string(), |gid| gid.to_string());
+        assert_eq!(lines[1], "default:user:1000:rwx\t#effective:r-x");
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    fs::OpenOptions,
+    io,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::Path,
+};
+
+/// Sort keys relating allocated to apparent size.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum AllocSort {
+    /// Apparent bytes that are not allocated (holes, compression).
+    #[default]
+    Saved,
+    /// Allocated bytes beyond the apparent size (preallocation).
+    Wasted,
+}
+impl<S: AsRef<str>> From<S> for AllocSort {
+    fn from(s: S) -> Self {
+        match s.as_ref().to_lowercase().as_str() {
+            "wasted" => Self::Wasted,
+            _ => Self::Saved,
+        }
+    }
+}
+impl AllocSort {
+    pub fn key(&self, size: u64, allocated: u64) -> u64 {
+        match self {
+            Self::Saved => size.saturating_sub(allocated),
+            Self::Wasted => allocated.saturating_sub(size),
+        }
+    }
+}
+
+/// Data segments and holes of `path`, walked with `SEEK_DATA`/`SEEK_HOLE`.
+/// Filesystems without hole support report a single data segment.
+pub fn get_segments(path: &Path, size: u64) -> io::Result<(u64, u64)> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW)
+        .open(path)?;
+    let fd = file.as_raw_fd();
+
+    let (mut data, mut holes, mut pos) = (0, 0, 0);
+    while pos < size as i64 {
+        let start = unsafe { libc::lseek(fd, pos, libc::SEEK_DATA) };
+        if start < 0 {
+            let err = io::Error::last_os_error();
+            // no data past `pos`: the rest of the file is one hole
+            if err.raw_os_error() == Some(libc::ENXIO) {
+                holes += 1;
+                break;
+            }
+            return Err(err);
+        }
+        let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+        if end < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if start > pos {
+            holes += 1;
+        }
+        data += 1;
+        pos = end;
+    }
+    Ok((data, holes))
+}
+
+/// Allocated size as a percentage of the apparent one, followed by `S` for
+/// sparse files and `P` for ones allocated past their last block. The marker
+/// is always one character wide so the `%` signs line up. Files smaller than
+/// a block get `-`: rounding up to one block would make every small file a
+/// four-digit percentage.
+pub fn format(size: u64, allocated: u64, blksize: u64) -> String {
+    let blksize = blksize.max(512);
+    let marker = if allocated < size {
+        'S'
+    } else if allocated > size.next_multiple_of(blksize) {
+        'P'
+    } else {
+        ' '
+    };
+    if size < blksize {
+        return format!("-{marker}");
+    }
+    format!("{}%{marker}", allocated as u128 * 100 / size as u128)
+}
+
+/// Data segments and holes as `Nd/Mh`.
+pub fn format_segments((data, holes): (u64, u64)) -> String {
+    format!("{data}d/{holes}h")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratios() {
+        assert_eq!(format(1 << 20, 1 << 20, 4096), "100% ");
+        assert_eq!(format(1 << 20, 0, 4096), "0%S");
+        assert_eq!(format(1 << 20, 1 << 19, 4096), "50%S");
+        assert_eq!(format(5000, 8192, 4096), "163% ");
+        assert_eq!(format(5000, 12288, 4096), "245%P");
+    }
+
+    #[test]
+    fn below_one_block() {
+        assert_eq!(format(0, 0, 4096), "- ");
+        assert_eq!(format(3, 4096, 4096), "- ");
+        assert_eq!(format(2, 65536, 4096), "-P");
+        assert_eq!(format(100, 0, 4096), "-S");
+    }
+}
@@ -0,0 +1,179 @@
+use std::{io, path::Path};
+
+use crate::xattr;
+
+pub const XATTR: &str = "security.capability";
+
+const REVISION_MASK: u32 = 0xff00_0000;
+const REVISION_1: u32 = 0x0100_0000;
+const REVISION_2: u32 = 0x0200_0000;
+const REVISION_3: u32 = 0x0300_0000;
+const FLAGS_EFFECTIVE: u32 = 0x0000_0001;
+
+const NAMES: [&str; 41] = [
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// File capabilities as stored in `security.capability` (`struct
+/// vfs_ns_cap_data`): a magic/flags word, then permitted and inheritable
+/// masks, and for revision 3 the root uid of the owning user namespace.
+#[derive(Debug, Clone)]
+pub struct Caps {
+    pub permitted: u64,
+    pub inheritable: u64,
+    pub effective: bool,
+    pub rootid: Option<u32>,
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn get_cap_name(cap: u32) -> String {
+    match NAMES.get(cap as usize) {
+        Some(name) => format!("cap_{name}"),
+        None => format!("cap_{cap}"),
+    }
+}
+
+impl Caps {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let magic = le32(bytes.get(..4)?);
+        let words = match magic & REVISION_MASK {
+            REVISION_1 => 1,
+            REVISION_2 | REVISION_3 => 2,
+            _ => return None,
+        };
+        let data = bytes.get(4..4 + words * 8)?;
+        let (mut permitted, mut inheritable) = (0, 0);
+        for (i, pair) in data.chunks_exact(8).enumerate() {
+            permitted |= (le32(pair) as u64) << (32 * i);
+            inheritable |= (le32(&pair[4..]) as u64) << (32 * i);
+        }
+        let rootid = match magic & REVISION_MASK {
+            REVISION_3 => Some(le32(bytes.get(20..24)?)),
+            _ => None,
+        };
+        Some(Self {
+            permitted,
+            inheritable,
+            effective: magic & FLAGS_EFFECTIVE != 0,
+            rootid,
+        })
+    }
+
+    /// Reads the capabilities of `path`, `None` if it has none.
+    pub fn read(path: &Path) -> io::Result<Option<Self>> {
+        match xattr::get(path, XATTR) {
+            Ok(bytes) => Ok(Self::parse(&bytes)),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ENODATA | libc::ENOTSUP)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The capabilities in `getcap` form, e.g. `cap_net_raw,cap_net_admin+ep`.
+    /// Capabilities with different sets are separated by spaces, and a
+    /// non-zero namespace root uid is appended as `[rootid=N]`.
+    pub fn format(&self) -> String {
+        let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+        for cap in 0..64 {
+            let bit = 1 << cap;
+            let mut flags = String::new();
+            if self.effective && self.permitted & bit != 0 {
+                flags.push('e');
+            }
+            if self.inheritable & bit != 0 {
+                flags.push('i');
+            }
+            if self.permitted & bit != 0 {
+                flags.push('p');
+            }
+            if flags.is_empty() {
+                continue;
+            }
+            match groups.iter_mut().find(|(f, _)| *f == flags) {
+                Some((_, caps)) => caps.push(get_cap_name(cap)),
+                None => groups.push((flags, vec![get_cap_name(cap)])),
+            }
+        }
+
+        let mut res = groups
+            .iter()
+            .map(|(flags, caps)| format!("{}+{}", caps.join(","), flags))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some(rootid) = self.rootid.filter(|&id| id != 0) {
+            res.push_str(&format!(" [rootid={rootid}]"));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v3(len: usize) -> Vec<u8> {
+        let mut bytes = (REVISION_3 | FLAGS_EFFECTIVE).to_le_bytes().to_vec();
+        bytes.extend((1u32 << 10 | 1 << 13).to_le_bytes()); // net_bind_service, net_raw
+        bytes.extend([0; 12]);
+        bytes.extend(1000u32.to_le_bytes());
+        bytes.truncate(len);
+        bytes
+    }
+
+    #[test]
+    fn parse_v3() {
+        let caps = Caps::parse(&v3(24)).unwrap();
+        assert_eq!(caps.permitted, 1 << 10 | 1 << 13);
+        assert!(caps.effective);
+        assert_eq!(caps.rootid, Some(1000));
+    }
+
+    #[test]
+    fn truncated_v3_is_rejected() {
+        for len in [0, 3, 12, 20, 23] {
+            assert!(Caps::parse(&v3(len)).is_none(), "length {len}");
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::Result;
+
+use crate::{
+    acl::{self, Acl},
+    get_groupname, get_mode, get_username,
+    stat::Stat,
+};
+
+fn get_type_name(st: &Stat) -> &'static str {
+    if st.is_dir() {
+        "directory"
+    } else if st.is_file() {
+        "regular file"
+    } else if st.is_symlink() {
+        "symbolic link"
+    } else if st.is_fifo() {
+        "named pipe"
+    } else if st.is_socket() {
+        "socket"
+    } else if st.is_block_device() {
+        "block device"
+    } else if st.is_char_device() {
+        "character device"
+    } else {
+        "unknown type"
+    }
+}
+
+fn get_perm_words(perm: u32, is_dir: bool) -> String {
+    let names = if is_dir {
+        [
+            "list entries",
+            "create, delete and rename entries",
+            "enter and look up entries",
+        ]
+    } else {
+        ["read", "write", "execute"]
+    };
+    let words: Vec<&str> = [4, 2, 1]
+        .into_iter()
+        .zip(names)
+        .filter(|(bit, _)| perm & bit != 0)
+        .map(|(_, w)| w)
+        .collect();
+    if words.is_empty() {
+        "nothing".to_string()
+    } else {
+        words.join(", ")
+    }
+}
+
+/// The process umask. `/proc/self/status` has it without the set-and-restore
+/// dance `umask(2)` needs.
+fn get_umask() -> u32 {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            let line = status.lines().find(|l| l.starts_with("Umask:"))?;
+            u32::from_str_radix(line["Umask:".len()..].trim(), 8).ok()
+        })
+        .unwrap_or_else(|| unsafe {
+            let mask = libc::umask(0o022);
+            libc::umask(mask);
+            mask
+        })
+}
+
+/// A plain-English account of what the permission bits of `path` allow.
+pub fn explain_mode(path: &Path) -> Result<String> {
+    let st = Stat::lstat(path, libc::STATX_BASIC_STATS)?;
+    let is_dir = st.is_dir();
+    let mode = st.mode & 0o7777;
+    let owner = get_username(st.uid)?;
+    let group = get_groupname(st.gid)?;
+    let access = Acl::read(path, acl::ACCESS)?.filter(Acl::is_extended);
+
+    let mut out = String::new();
+    writeln!(out, "{}: {}", path.display(), get_type_name(&st))?;
+    writeln!(out, "mode {} ({:04o})", get_mode(&st), mode)?;
+    writeln!(out)?;
+
+    if st.is_symlink() {
+        writeln!(
+            out,
+            "The permissions of a symbolic link are never checked; what counts is the \
+             mode of the file it points to."
+        )?;
+        return Ok(out);
+    }
+
+    writeln!(
+        out,
+        "owner {owner}: {}",
+        get_perm_words(mode >> 6 & 0o7, is_dir)
+    )?;
+    if access.is_some() {
+        writeln!(
+            out,
+            "group bits (ACL mask): at most {}",
+            get_perm_words(mode >> 3 & 0o7, is_dir)
+        )?;
+    } else {
+        writeln!(
+            out,
+            "group {group}: {}",
+            get_perm_words(mode >> 3 & 0o7, is_dir)
+        )?;
+    }
+    writeln!(out, "others: {}", get_perm_words(mode & 0o7, is_dir))?;
+    if is_dir && mode & 0o111 != 0o111 && mode & 0o444 != 0 {
+        writeln!(
+            out,
+            "Note that listing a directory without being able to enter it only shows names."
+        )?;
+    }
+
+    let mut special = Vec::new();
+    if mode & 0o4000 != 0 {
+        special.push(match (is_dir, mode & 0o100 != 0) {
+            (true, _) => "setuid: ignored on directories by Linux.".to_string(),
+            (false, true) => format!("setuid: the program runs as its owner, {owner}."),
+            (false, false) => {
+                "setuid (S): set, but without owner execute it has no effect.".to_string()
+            }
+        });
+    }
+    if mode & 0o2000 != 0 {
+        special.push(match (is_dir, mode & 0o010 != 0) {
+            (true, _) => format!(
+                "setgid: new entries inherit the group {group}, and new subdirectories \
+                 inherit setgid."
+            ),
+            (false, true) => format!("setgid: the program runs with the group {group}."),
+            (false, false) => "setgid (S): set without group execute it has no effect \
+                               (kernels before 5.15 used it to mark mandatory locking)."
+                .to_string(),
+        });
+    }
+    if mode & 0o1000 != 0 {
+        special.push(match (is_dir, mode & 0o001 != 0) {
+            (true, true) => "sticky (t): only the owner of an entry, the owner of the \
+                             directory or root may delete or rename it."
+                .to_string(),
+            (true, false) => "sticky (T): restricts deletion to entry owners, but others \
+                              cannot enter the directory anyway."
+                .to_string(),
+            (false, _) => "sticky: ignored on files by Linux.".to_string(),
+        });
+    }
+    if !special.is_empty() {
+        writeln!(out)?;
+        for line in special {
+            writeln!(out, "{line}")?;
+        }
+    }
+
+    if let Some(acl) = &access {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "An access ACL overrides the group bits: they now show the mask, the most \
+             that named users and groups can get."
+        )?;
+        let user = |uid| get_username(uid).unwrap_or_else(|_| uid.to_string());
+        let group = |gid| get_groupname(gid).unwrap_or_else(|_| gid.to_string());
+        for line in acl.to_lines("  ", user, group) {
+            writeln!(out, "{line}")?;
+        }
+    }
+
+    writeln!(out)?;
+    let umask = get_umask();
+    let default = if is_dir {
+        Acl::read(path, acl::DEFAULT)?
+    } else {
+        None
+    };
+    if let Some(default) = default {
+        writeln!(
+            out,
+            "The default ACL replaces the umask for entries created here; they start from:"
+        )?;
+        let user = |uid| get_username(uid).unwrap_or_else(|_| uid.to_string());
+        let group = |gid| get_groupname(gid).unwrap_or_else(|_| gid.to_string());
+        for line in default.to_lines("  ", user, group) {
+            writeln!(out, "{line}")?;
+        }
+    } else {
+        writeln!(
+            out,
+            "With the current umask {:04o}, new files get at most {:04o} and new \
+             directories {:04o}; an existing mode like this one is not affected by it.",
+            umask,
+            0o666 & !umask,
+            0o777 & !umask
+        )?;
+    }
+
+    Ok(out)
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs::OpenOptions,
+    io, mem,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::Path,
+};
+
+const FIEMAP_EXTENT_LAST: u32 = 0x0001;
+const FIEMAP_EXTENT_DATA_INLINE: u32 = 0x0200;
+const FIEMAP_EXTENT_UNWRITTEN: u32 = 0x0800;
+const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+
+/// Extents fetched per ioctl call.
+const BATCH: usize = 128;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FiemapExtent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    _reserved64: [u64; 2],
+    flags: u32,
+    _reserved: [u32; 3],
+}
+
+/// `struct fiemap` without its trailing extent array, which is what the
+/// ioctl number is encoded with.
+#[repr(C)]
+struct FiemapHeader {
+    start: u64,
+    length: u64,
+    flags: u32,
+    mapped_extents: u32,
+    extent_count: u32,
+    _reserved: u32,
+}
+
+#[repr(C)]
+struct Fiemap {
+    header: FiemapHeader,
+    extents: [FiemapExtent; BATCH],
+}
+
+/// Extent layout of a file as reported by `FS_IOC_FIEMAP`.
+#[derive(Debug, Default)]
+pub struct Extents {
+    pub count: u64,
+    pub shared: u64,
+    pub inline: u64,
+    pub unwritten: u64,
+    /// Physical (offset, length) of every shared extent, to find out how much
+    /// of the sharing happens between listed files.
+    pub shared_extents: Vec<(u64, u64)>,
+}
+
+/// Maps the extents of `path`. `None` when the filesystem does not implement
+/// FIEMAP (tmpfs, network filesystems).
+pub fn get_extents(path: &Path) -> io::Result<Option<Extents>> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW)
+        .open(path)?;
+    match read_extents(file.as_raw_fd()) {
+        Err(e) if matches!(e.raw_os_error(), Some(libc::EOPNOTSUPP | libc::ENOTTY)) => Ok(None),
+        res => res.map(Some),
+    }
+}
+
+fn read_extents(fd: libc::c_int) -> io::Result<Extents> {
+    let mut res = Extents::default();
+    let mut map: Box<Fiemap> = Box::new(unsafe { mem::zeroed() });
+    let mut start = 0;
+    loop {
+        map.header = FiemapHeader {
+            start,
+            length: u64::MAX - start,
+            // no FIEMAP_FLAG_SYNC: a listing should not force writeback, so
+            // freshly written data may still show as delayed allocation
+            flags: 0,
+            mapped_extents: 0,
+            extent_count: BATCH as u32,
+            _reserved: 0,
+        };
+        let ret = unsafe {
+            libc::ioctl(
+                fd,
+                libc::_IOWR::<FiemapHeader>('f' as u32, 11),
+                &mut *map as *mut Fiemap,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mapped = &map.extents[..map.header.mapped_extents as usize];
+        for ext in mapped {
+            res.count += 1;
+            if ext.flags & FIEMAP_EXTENT_SHARED != 0 {
+                res.shared += ext.length;
+                res.shared_extents.push((ext.physical, ext.length));
+            }
+            if ext.flags & FIEMAP_EXTENT_DATA_INLINE != 0 {
+                res.inline += ext.length;
+            }
+            if ext.flags & FIEMAP_EXTENT_UNWRITTEN != 0 {
+                res.unwritten += ext.length;
+            }
+        }
+        match mapped.last() {
+            Some(last) if last.flags & FIEMAP_EXTENT_LAST == 0 => {
+                start = last.logical + last.length;
+            }
+            _ => return Ok(res),
+        }
+    }
+}
+
+/// Bytes of shared extents referenced by the listed files, and how many of
+/// them are saved because several of those files point at the same blocks.
+/// Extents may overlap only partly, so the saving is the referenced bytes
+/// minus the size of their union.
+pub fn get_reflink_savings<'a>(all: impl Iterator<Item = &'a Extents>) -> (u64, u64) {
+    let mut ranges: Vec<(u64, u64)> = all
+        .flat_map(|e| &e.shared_extents)
+        .map(|&(physical, length)| (physical, physical.saturating_add(length)))
+        .collect();
+    ranges.sort_unstable();
+
+    let shared = ranges.iter().map(|(start, end)| end - start).sum();
+    let (mut union, mut covered) = (0, 0);
+    for (start, end) in ranges {
+        let start = start.max(covered);
+        if end > start {
+            union += end - start;
+        }
+        covered = covered.max(end);
+    }
+    (shared, shared - union)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared(extents: &[(u64, u64)]) -> Extents {
+        Extents {
+            count: extents.len() as u64,
+            shared: extents.iter().map(|e| e.1).sum(),
+            shared_extents: extents.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reflink_savings() {
+        let a = shared(&[(0, 8192), (65536, 4096)]);
+        let b = shared(&[(0, 8192)]);
+        assert_eq!(get_reflink_savings([&a, &b].into_iter()), (20480, 8192));
+    }
+
+    #[test]
+    fn reflink_savings_partial_overlap() {
+        // b shares the second half of a's extent and runs on past it
+        let a = shared(&[(0, 8192)]);
+        let b = shared(&[(4096, 8192)]);
+        let c = shared(&[(4096, 4096)]);
+        assert_eq!(get_reflink_savings([&a, &b].into_iter()), (16384, 4096));
+        assert_eq!(get_reflink_savings([&a, &b, &c].into_iter()), (20480, 8192));
+    }
+
+    #[test]
+    fn reflink_savings_disjoint() {
+        let a = shared(&[(0, 4096)]);
+        let b = shared(&[(8192, 4096)]);
+        assert_eq!(get_reflink_savings([&a, &b].into_iter()), (8192, 0));
+        assert_eq!(get_reflink_savings(std::iter::empty()), (0, 0));
+    }
+}
@@ -0,0 +1,259 @@
+use std::{fs, path::PathBuf, str::FromStr, time::SystemTime};
+
+use crate::{FEntry, FType, LssConf, Time, get_unit_multiplier, query::Query};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeFilter {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    Block,
+    Char,
+}
+impl FromStr for TypeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f" => Ok(Self::File),
+            "d" => Ok(Self::Dir),
+            "l" => Ok(Self::Symlink),
+            "p" => Ok(Self::Fifo),
+            "s" => Ok(Self::Socket),
+            "b" => Ok(Self::Block),
+            "c" => Ok(Self::Char),
+            _ => Err(format!("unknown type `{s}`, expected one of f,d,l,p,s,b,c")),
+        }
+    }
+}
+impl TypeFilter {
+    pub fn matches(&self, ftype: &FType) -> bool {
+        matches!(
+            (self, ftype),
+            (Self::File, FType::File(_))
+                | (Self::Dir, FType::Dir)
+                | (Self::Symlink, FType::Symlink(_) | FType::BrokenSymlink)
+                | (Self::Fifo, FType::Fifo)
+                | (Self::Socket, FType::Socket)
+                | (Self::Block, FType::BlockDevice)
+                | (Self::Char, FType::CharDevice)
+        )
+    }
+}
+
+/// Parses sizes in the units printed by `get_human_readable_size` (`512`, `10K`, `1.5G`,
+/// `2MB`, `1GiB`).
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: f64 = num.parse().map_err(|_| format!("invalid size `{s}`"))?;
+
+    let mult = get_unit_multiplier(unit).ok_or(format!("unknown size unit `{unit}`"))?;
+
+    Ok((num * mult as f64) as u64)
+}
+
+/// Parses durations such as `7d`, `3h` or `1w2d12h` into seconds. `None` for
+/// empty input and for durations that do not fit in a `u64`.
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    let mut total: u64 = 0;
+    let mut num = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        let n: u64 = num.parse().ok()?;
+        num.clear();
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 7 * 86400,
+            'y' => 365 * 86400,
+            _ => return None,
+        };
+        total = total.checked_add(n.checked_mul(unit)?)?;
+    }
+    if num.is_empty() { Some(total) } else { None }
+}
+
+/// Parses `YYYY-MM-DD`, optionally followed by `HH:MM[:SS]` (space or `T` separated),
+/// in local time.
+fn parse_date(s: &str) -> Option<Time> {
+    let (date, time) = match s.trim().split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (s.trim(), None),
+    };
+
+    let mut date = date.split('-').map(|p| p.parse::<u32>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    if date.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (mut hour, mut min, mut sec) = (0, 0, 0);
+    if let Some(time) = time {
+        let mut time = time.split(':').map(|p| p.parse::<u32>().ok());
+        hour = time.next()??;
+        min = time.next()??;
+        sec = time.next().unwrap_or(Some(0))?;
+        if hour > 23 || min > 59 || sec > 60 {
+            return None;
+        }
+    }
+
+    Some(Time::from_calendar(year as i32, month, day, hour, min, sec))
+}
+
+/// A point in time given as a duration back from now, an absolute date, or a
+/// reference file whose modification time is used.
+pub fn parse_time(s: &str) -> Result<Time, String> {
+    if let Some(secs) = parse_duration(s) {
+        let now = SystemTime::now();
+        return Ok(Time::from(
+            now - std::time::Duration::from_secs(secs).min(
+                now.duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default(),
+            ),
+        ));
+    }
+    if let Some(time) = parse_date(s) {
+        return Ok(time);
+    }
+    match fs::metadata(s) {
+        Ok(md) => Time::from_modified(&md).map_err(|e| e.to_string()),
+        Err(_) => Err(format!(
+            "`{s}` is neither a duration, a date (YYYY-MM-DD [HH:MM]) nor an existing file"
+        )),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Filter {
+    types: Vec<TypeFilter>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    newer: Option<Time>,
+    older: Option<Time>,
+    locked: bool,
+    query: Option<Query>,
+    root: PathBuf,
+}
+
+impl Filter {
+    pub fn from_conf(conf: &LssConf) -> Self {
+        Self {
+            types: conf.types.clone(),
+            min_size: conf.min_size,
+            max_size: conf.max_size,
+            newer: conf.newer.clone(),
+            older: conf.older.clone(),
+            locked: conf.locked,
+            query: conf.query.clone(),
+            root: PathBuf::from(&conf.path),
+        }
+    }
+    pub fn matches(&self, fe: &FEntry) -> bool {
+        if !self.types.is_empty() && !self.types.iter().any(|t| t.matches(&fe.ftype)) {
+            return false;
+        }
+        if self.min_size.is_some_and(|min| fe.size < min)
+            || self.max_size.is_some_and(|max| fe.size > max)
+        {
+            return false;
+        }
+        if self.newer.as_ref().is_some_and(|t| fe.modified <= *t)
+            || self.older.as_ref().is_some_and(|t| fe.modified >= *t)
+        {
+            return false;
+        }
+        if self.locked && fe.locks.is_empty() {
+            return false;
+        }
+        self.query
+            .as_ref()
+            .is_none_or(|q| q.matches(fe, &self.root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s"), Some(90));
+        assert_eq!(parse_duration(" 3h "), Some(3 * 3600));
+        assert_eq!(parse_duration("1w2d12h"), Some(9 * 86400 + 12 * 3600));
+        assert_eq!(parse_duration("1y"), Some(365 * 86400));
+        assert_eq!(parse_duration("0m"), Some(0));
+    }
+
+    #[test]
+    fn bad_durations() {
+        for s in ["", "  ", "7", "d", "7x", "1d2", "-1d", "1.5h"] {
+            assert_eq!(parse_duration(s), None, "{s:?}");
+        }
+        assert_eq!(parse_duration("999999999999999999y"), None);
+        assert_eq!(parse_duration("99999999999999w"), None);
+        assert_eq!(parse_duration("18446744073709551615s1s"), None);
+    }
+
+    #[test]
+    fn empty_time_is_an_error() {
+        assert!(parse_time("").is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("10k"), Ok(10 * 1024));
+        assert_eq!(parse_size("1.5G"), Ok(3 << 29));
+        assert_eq!(parse_size("2MB"), Ok(2_000_000));
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        assert_eq!(parse_size("3B"), Ok(3));
+        for s in ["", "K", "10Q", "10KiBs", "1.2.3"] {
+            assert!(parse_size(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn dates() {
+        let secs = |s| parse_date(s).map(|t| t.secs());
+        let at = |y, mo, d, h, mi, s| Some(Time::from_calendar(y, mo, d, h, mi, s).secs());
+        assert_eq!(secs("2024-01-31"), at(2024, 1, 31, 0, 0, 0));
+        assert_eq!(secs("2024-02-29 13:05"), at(2024, 2, 29, 13, 5, 0));
+        assert_eq!(secs("2024-02-29T13:05:07"), at(2024, 2, 29, 13, 5, 7));
+        assert_eq!(
+            secs("2024-01-02").unwrap() - secs("2024-01-01").unwrap(),
+            86400
+        );
+        for s in [
+            "",
+            "2024",
+            "2024-01",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-01-32",
+            "2024-01-01-01",
+            "2024-01-01 24:00",
+            "2024-01-01 12",
+            "2024-01-01 12:60",
+            "yesterday",
+        ] {
+            assert!(parse_date(s).is_none(), "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    fs::OpenOptions,
+    io,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::Path,
+};
+
+/// Inode flags in the order `lsattr` prints them.
+const FLAGS: [(u32, char); 22] = [
+    (0x0000_0001, 's'), // secure deletion
+    (0x0000_0002, 'u'), // undelete
+    (0x0000_0008, 'S'), // synchronous updates
+    (0x0001_0000, 'D'), // synchronous directory updates
+    (0x0000_0010, 'i'), // immutable
+    (0x0000_0020, 'a'), // append only
+    (0x0000_0040, 'd'), // no dump
+    (0x0000_0080, 'A'), // no atime updates
+    (0x0000_0004, 'c'), // compressed
+    (0x0000_0800, 'E'), // encrypted
+    (0x0000_4000, 'j'), // data journalling
+    (0x0000_1000, 'I'), // hashed directory index
+    (0x0000_8000, 't'), // no tail merging
+    (0x0002_0000, 'T'), // top of directory hierarchy
+    (0x0008_0000, 'e'), // extents
+    (0x0080_0000, 'C'), // no copy on write
+    (0x0200_0000, 'x'), // direct access
+    (0x4000_0000, 'F'), // casefolded
+    (0x1000_0000, 'N'), // inline data
+    (0x2000_0000, 'P'), // project id inheritance
+    (0x0010_0000, 'V'), // verity
+    (0x0000_0400, 'm'), // no compression
+];
+
+/// Width of the `lsattr` flag string.
+pub const WIDTH: usize = FLAGS.len();
+
+/// `FS_IOC_GETFLAGS` of `path`, which has to be a regular file or directory
+/// since anything else would have to be opened. `None` when the filesystem
+/// does not implement the ioctl.
+pub fn get_inode_flags(path: &Path) -> io::Result<Option<u32>> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW | libc::O_NOCTTY)
+        .open(path)?;
+
+    let mut flags: libc::c_int = 0;
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) };
+    if ret == 0 {
+        return Ok(Some(flags as u32));
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::ENOTTY | libc::ENOTSUP | libc::EINVAL) => Ok(None),
+        _ => Err(err),
+    }
+}
+
+/// `flags` as `lsattr` prints them, e.g. `----i---------e-------`.
+pub fn to_lsattr(flags: u32) -> String {
+    FLAGS
+        .iter()
+        .map(|&(bit, c)| if flags & bit != 0 { c } else { '-' })
+        .collect()
+}
+
+/// Parses `chattr` letters (`ia`) into the matching flag bits.
+pub fn parse_letters(s: &str) -> Result<u32, String> {
+    s.chars()
+        .try_fold(0, |acc, c| match FLAGS.iter().find(|&&(_, f)| f == c) {
+            Some((bit, _)) => Ok(acc | bit),
+            None => Err(format!(
+                "unknown attribute `{c}`, expected one of suSDiadAcEjItTeCxFNPVm"
+            )),
+        })
+}
@@ -0,0 +1,971 @@
+use anyhow::Result;
+use anyhow::anyhow;
+use flate2::bufread::ZlibDecoder;
+use log::{info, warn};
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufReader, Read, Seek, SeekFrom},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+};
+
+type Oid = [u8; 20];
+
+const MODE_GITLINK: u32 = 0o160000;
+const MODE_SYMLINK: u32 = 0o120000;
+
+fn parse_hex_oid(s: &str) -> Option<Oid> {
+    let s = s.trim();
+    if s.len() != 40 {
+        return None;
+    }
+    let mut oid = [0u8; 20];
+    for (i, b) in oid.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(oid)
+}
+
+fn to_hex(oid: &Oid) -> String {
+    oid.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn be32(data: &[u8], at: usize) -> Result<u32> {
+    let bytes = data
+        .get(at..at + 4)
+        .ok_or(anyhow!("unexpected end of data"))?;
+    Ok(u32::from_be_bytes(bytes.try_into()?))
+}
+
+fn be16(data: &[u8], at: usize) -> Result<u16> {
+    let bytes = data
+        .get(at..at + 2)
+        .ok_or(anyhow!("unexpected end of data"))?;
+    Ok(u16::from_be_bytes(bytes.try_into()?))
+}
+
+/// Offset encoding shared by index v4 path prefixes and `OFS_DELTA` pack entries.
+fn read_offset_varint(mut next: impl FnMut() -> Result<u8>) -> Result<u64> {
+    let mut c = next()?;
+    let mut val = (c & 0x7f) as u64;
+    while c & 0x80 != 0 {
+        c = next()?;
+        val = ((val + 1) << 7) | (c & 0x7f) as u64;
+    }
+    Ok(val)
+}
+
+fn hash_blob(data: &[u8]) -> Oid {
+    let mut sha = sha1_smol::Sha1::new();
+    sha.update(format!("blob {}\0", data.len()).as_bytes());
+    sha.update(data);
+    sha.digest().bytes()
+}
+
+struct IndexEntry {
+    path: String,
+    oid: Oid,
+    mode: u32,
+    mtime: (u32, u32),
+    size: u32,
+    stage: u8,
+    skip_worktree: bool,
+}
+
+fn parse_index(data: &[u8]) -> Result<Vec<IndexEntry>> {
+    if data.get(0..4) != Some(b"DIRC") {
+        return Err(anyhow!("bad index signature"));
+    }
+    let version = be32(data, 4)?;
+    if !(2..=4).contains(&version) {
+        return Err(anyhow!("unsupported index version {version}"));
+    }
+    let count = be32(data, 8)? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut pos = 12;
+    let mut prev_path: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let start = pos;
+        let mtime = (be32(data, pos + 8)?, be32(data, pos + 12)?);
+        let mode = be32(data, pos + 24)?;
+        let size = be32(data, pos + 36)?;
+        let oid: Oid = data
+            .get(pos + 40..pos + 60)
+            .ok_or(anyhow!("unexpected end of index"))?
+            .try_into()?;
+        let flags = be16(data, pos + 60)?;
+        pos += 62;
+
+        let mut skip_worktree = false;
+        if version >= 3 && flags & 0x4000 != 0 {
+            let ext = be16(data, pos)?;
+            skip_worktree = ext & 0x4000 != 0;
+            pos += 2;
+        }
+
+        let path = if version == 4 {
+            let strip = read_offset_varint(|| {
+                let b = *data.get(pos).ok_or(anyhow!("unexpected end of index"))?;
+                pos += 1;
+                Ok(b)
+            })? as usize;
+            let rest = data.get(pos..).ok_or(anyhow!("unexpected end of index"))?;
+            let end = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or(anyhow!("unterminated index path"))?;
+            let keep = prev_path.len().saturating_sub(strip);
+            prev_path.truncate(keep);
+            prev_path.extend_from_slice(&rest[..end]);
+            pos += end + 1;
+            prev_path.clone()
+        } else {
+            let rest = data.get(pos..).ok_or(anyhow!("unexpected end of index"))?;
+            let end = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or(anyhow!("unterminated index path"))?;
+            let path = rest[..end].to_vec();
+            // entries are NUL-padded to a multiple of 8 bytes
+            pos = start + (pos - start + end + 8) / 8 * 8;
+            path
+        };
+
+        entries.push(IndexEntry {
+            path: String::from_utf8_lossy(&path).into_owned(),
+            oid,
+            mode,
+            mtime,
+            size,
+            stage: ((flags >> 12) & 3) as u8,
+            skip_worktree,
+        });
+    }
+
+    Ok(entries)
+}
+
+struct Pack {
+    path: PathBuf,
+    idx: Vec<u8>,
+}
+
+impl Pack {
+    fn find(&self, oid: &Oid) -> Option<u64> {
+        let idx = &self.idx;
+        if idx.get(0..8) != Some(&[0xff, b't', b'O', b'c', 0, 0, 0, 2]) {
+            return None;
+        }
+        let fanout = |i: usize| be32(idx, 8 + i * 4).ok().map(|v| v as usize);
+        let count = fanout(255)?;
+        let lo = if oid[0] == 0 {
+            0
+        } else {
+            fanout(oid[0] as usize - 1)?
+        };
+        let hi = fanout(oid[0] as usize)?;
+
+        let shas = 8 + 256 * 4;
+        let (mut lo, mut hi) = (lo, hi);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let sha = idx.get(shas + mid * 20..shas + mid * 20 + 20)?;
+            match sha.cmp(oid) {
+                std::cmp::Ordering::Equal => {
+                    let offsets = shas + count * 24;
+                    let off = be32(idx, offsets + mid * 4).ok()?;
+                    if off & 0x8000_0000 == 0 {
+                        return Some(off as u64);
+                    }
+                    let large = offsets + count * 4 + (off & 0x7fff_ffff) as usize * 8;
+                    let bytes = idx.get(large..large + 8)?;
+                    return Some(u64::from_be_bytes(bytes.try_into().ok()?));
+                }
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        None
+    }
+}
+
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let mut size = || -> Result<usize> {
+        let mut val = 0usize;
+        let mut shift = 0;
+        loop {
+            let c = *delta.get(pos).ok_or(anyhow!("truncated delta"))?;
+            pos += 1;
+            val |= ((c & 0x7f) as usize) << shift;
+            shift += 7;
+            if c & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+    };
+    let base_size = size()?;
+    let result_size = size()?;
+    if base_size != base.len() {
+        return Err(anyhow!("delta base size mismatch"));
+    }
+
+    let mut out = Vec::with_capacity(result_size);
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            let mut off = 0usize;
+            let mut len = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    off |= (*delta.get(pos).ok_or(anyhow!("truncated delta"))? as usize) << (i * 8);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    len |= (*delta.get(pos).ok_or(anyhow!("truncated delta"))? as usize) << (i * 8);
+                    pos += 1;
+                }
+            }
+            if len == 0 {
+                len = 0x10000;
+            }
+            out.extend_from_slice(
+                base.get(off..off + len)
+                    .ok_or(anyhow!("delta copy out of range"))?,
+            );
+        } else if op != 0 {
+            let len = op as usize;
+            out.extend_from_slice(
+                delta
+                    .get(pos..pos + len)
+                    .ok_or(anyhow!("truncated delta"))?,
+            );
+            pos += len;
+        } else {
+            return Err(anyhow!("reserved delta opcode"));
+        }
+    }
+
+    if out.len() != result_size {
+        return Err(anyhow!("delta result size mismatch"));
+    }
+    Ok(out)
+}
+
+struct Odb {
+    objects: PathBuf,
+    packs: Vec<Pack>,
+}
+
+impl Odb {
+    fn open(objects: PathBuf) -> Self {
+        let mut packs = Vec::new();
+        if let Ok(dir) = fs::read_dir(objects.join("pack")) {
+            for f in dir.flatten() {
+                let path = f.path();
+                if path.extension().is_some_and(|e| e == "idx")
+                    && let Ok(idx) = fs::read(&path)
+                {
+                    packs.push(Pack {
+                        path: path.with_extension("pack"),
+                        idx,
+                    });
+                }
+            }
+        }
+        info!("git: {} pack(s) in `{}`", packs.len(), objects.display());
+        Self { objects, packs }
+    }
+
+    fn read(&self, oid: &Oid) -> Result<(u8, Vec<u8>)> {
+        let hex = to_hex(oid);
+        let loose = self.objects.join(&hex[..2]).join(&hex[2..]);
+        if let Ok(raw) = fs::read(&loose) {
+            let mut data = Vec::new();
+            ZlibDecoder::new(&raw[..]).read_to_end(&mut data)?;
+            let nul = data
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or(anyhow!("bad loose object header"))?;
+            let kind = match data.split(|&b| b == b' ').next() {
+                Some(b"commit") => 1,
+                Some(b"tree") => 2,
+                Some(b"blob") => 3,
+                Some(b"tag") => 4,
+                _ => return Err(anyhow!("unknown loose object type")),
+            };
+            return Ok((kind, data.split_off(nul + 1)));
+        }
+
+        for pack in &self.packs {
+            if let Some(offset) = pack.find(oid) {
+                return self.read_packed(pack, offset);
+            }
+        }
+        Err(anyhow!("object {hex} not found"))
+    }
+
+    fn read_packed(&self, pack: &Pack, offset: u64) -> Result<(u8, Vec<u8>)> {
+        let mut file = File::open(&pack.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(file);
+        let mut byte = || -> Result<u8> {
+            let mut b = [0u8; 1];
+            reader.read_exact(&mut b)?;
+            Ok(b[0])
+        };
+
+        let mut c = byte()?;
+        let kind = (c >> 4) & 7;
+        let mut size = (c & 0x0f) as u64;
+        let mut shift = 4;
+        while c & 0x80 != 0 {
+            c = byte()?;
+            size |= ((c & 0x7f) as u64) << shift;
+            shift += 7;
+        }
+
+        let base = match kind {
+            6 => {
+                let back = read_offset_varint(&mut byte)?;
+                let base = offset
+                    .checked_sub(back)
+                    .ok_or(anyhow!("delta base before start of pack"))?;
+                Some(self.read_packed(pack, base)?)
+            }
+            7 => {
+                let mut base = [0u8; 20];
+                for b in base.iter_mut() {
+                    *b = byte()?;
+                }
+                Some(self.read(&base)?)
+            }
+            _ => None,
+        };
+
+        let mut data = Vec::with_capacity(size as usize);
+        ZlibDecoder::new(reader).take(size).read_to_end(&mut data)?;
+
+        match base {
+            Some((base_kind, base_data)) => Ok((base_kind, apply_delta(&base_data, &data)?)),
+            None => Ok((kind, data)),
+        }
+    }
+
+    fn collect_tree(
+        &self,
+        oid: &Oid,
+        prefix: &str,
+        out: &mut HashMap<String, (u32, Oid)>,
+    ) -> Result<()> {
+        let (kind, data) = self.read(oid)?;
+        if kind != 2 {
+            return Err(anyhow!("object {} is not a tree", to_hex(oid)));
+        }
+
+        let mut pos = 0;
+        while pos < data.len() {
+            let sp = data[pos..]
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or(anyhow!("bad tree entry"))?;
+            let mode = u32::from_str_radix(std::str::from_utf8(&data[pos..pos + sp])?, 8)?;
+            pos += sp + 1;
+            let nul = data[pos..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or(anyhow!("bad tree entry"))?;
+            let name = String::from_utf8_lossy(&data[pos..pos + nul]);
+            pos += nul + 1;
+            let child: Oid = data
+                .get(pos..pos + 20)
+                .ok_or(anyhow!("bad tree entry"))?
+                .try_into()?;
+            pos += 20;
+
+            let path = format!("{prefix}{name}");
+            if mode == 0o40000 {
+                self.collect_tree(&child, &format!("{path}/"), out)?;
+            } else {
+                out.insert(path, (mode, child));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Rule {
+    base: String,
+    pattern: Vec<u8>,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(base: &str, line: &str) -> Option<Self> {
+        let line = line.trim_end_matches(['\r', ' ']);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            base: base.to_string(),
+            pattern: line.as_bytes().to_vec(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let rest = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(self.base.as_str())
+                .and_then(|p| p.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => return false,
+            }
+        };
+        if self.anchored {
+            wildmatch(&self.pattern, rest.as_bytes())
+        } else {
+            let name = rest.rsplit('/').next().unwrap_or(rest);
+            wildmatch(&self.pattern, name.as_bytes())
+        }
+    }
+}
+
+fn match_class(p: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(p.get(i), Some(b'!') | Some(b'^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < p.len() {
+        if p[i] == b']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        let lo = p[i];
+        if p.get(i + 1) == Some(&b'-') && p.get(i + 2).is_some_and(|&hi| hi != b']') {
+            matched |= (lo..=p[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Gitignore-flavoured glob: `*` and `?` stop at `/`, `**` crosses directories.
+pub fn wildmatch(p: &[u8], t: &[u8]) -> bool {
+    match p.first() {
+        None => t.is_empty(),
+        Some(b'*') if p.get(1) == Some(&b'*') => {
+            let rest = &p[2..];
+            if let Some(rest) = rest.strip_prefix(b"/") {
+                wildmatch(rest, t)
+                    || (0..t.len()).any(|i| t[i] == b'/' && wildmatch(rest, &t[i + 1..]))
+            } else {
+                (0..=t.len()).any(|i| wildmatch(rest, &t[i..]))
+            }
+        }
+        Some(b'*') => {
+            let rest = &p[1..];
+            for i in 0..=t.len() {
+                if wildmatch(rest, &t[i..]) {
+                    return true;
+                }
+                if i < t.len() && t[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => !t.is_empty() && t[0] != b'/' && wildmatch(&p[1..], &t[1..]),
+        Some(b'[') if !t.is_empty() => match match_class(p, t[0]) {
+            Some((true, len)) => wildmatch(&p[len..], &t[1..]),
+            Some((false, _)) => false,
+            None => t[0] == b'[' && wildmatch(&p[1..], &t[1..]),
+        },
+        Some(b'\\') if p.len() > 1 => !t.is_empty() && t[0] == p[1] && wildmatch(&p[2..], &t[1..]),
+        Some(&c) => !t.is_empty() && t[0] == c && wildmatch(&p[1..], &t[1..]),
+    }
+}
+
+/// Working-tree and index status of the entries of one directory, read straight
+/// from `.git` without calling out to the `git` binary.
+pub struct GitStatus {
+    workdir: PathBuf,
+    dir: String,
+    index: HashMap<String, IndexEntry>,
+    index_mtime: (i64, i64),
+    conflicted: HashSet<String>,
+    head: HashMap<String, (u32, Oid)>,
+    renamed: HashSet<String>,
+    excludes: Vec<Rule>,
+    gitignores: RefCell<HashMap<String, Vec<Rule>>>,
+}
+
+impl GitStatus {
+    fn discover(dir: &Path) -> Option<(PathBuf, PathBuf)> {
+        let mut cur = Some(dir);
+        while let Some(d) = cur {
+            let dotgit = d.join(".git");
+            if dotgit.is_dir() {
+                return Some((d.to_path_buf(), dotgit));
+            }
+            if dotgit.is_file() {
+                let content = fs::read_to_string(&dotgit).ok()?;
+                let gitdir = content.strip_prefix("gitdir:")?.trim();
+                return Some((d.to_path_buf(), d.join(gitdir)));
+            }
+            cur = d.parent();
+        }
+        None
+    }
+
+    fn resolve_ref(gitdir: &Path, common: &Path, name: &str) -> Option<Oid> {
+        let mut name = name.to_string();
+        for _ in 0..10 {
+            let content = fs::read_to_string(gitdir.join(&name))
+                .or_else(|_| fs::read_to_string(common.join(&name)));
+            match content {
+                Ok(content) => match content.trim().strip_prefix("ref:") {
+                    Some(target) => name = target.trim().to_string(),
+                    None => return parse_hex_oid(&content),
+                },
+                Err(_) => {
+                    let packed = fs::read_to_string(common.join("packed-refs")).ok()?;
+                    return packed
+                        .lines()
+                        .filter(|l| !l.starts_with('#') && !l.starts_with('^'))
+                        .filter_map(|l| l.split_once(' '))
+                        .find(|(_, r)| *r == name)
+                        .and_then(|(oid, _)| parse_hex_oid(oid));
+                }
+            }
+        }
+        None
+    }
+
+    fn read_head_tree(
+        odb: &Odb,
+        gitdir: &Path,
+        common: &Path,
+    ) -> Result<HashMap<String, (u32, Oid)>> {
+        let mut head = HashMap::new();
+        let Some(commit) = Self::resolve_ref(gitdir, common, "HEAD") else {
+            info!("git: HEAD is unborn");
+            return Ok(head);
+        };
+        let (kind, data) = odb.read(&commit)?;
+        if kind != 1 {
+            return Err(anyhow!("HEAD is not a commit"));
+        }
+        let tree = std::str::from_utf8(&data)?
+            .lines()
+            .next()
+            .and_then(|l| l.strip_prefix("tree "))
+            .and_then(parse_hex_oid)
+            .ok_or(anyhow!("commit without tree"))?;
+        odb.collect_tree(&tree, "", &mut head)?;
+        Ok(head)
+    }
+
+    pub fn for_dir<P: AsRef<Path>>(dir: P) -> Option<Self> {
+        let abs = fs::canonicalize(dir).ok()?;
+        let (workdir, gitdir) = Self::discover(&abs)?;
+        let dir = abs
+            .strip_prefix(&workdir)
+            .ok()?
+            .to_string_lossy()
+            .into_owned();
+        if dir == ".git" || dir.starts_with(".git/") {
+            return None;
+        }
+        let common = match fs::read_to_string(gitdir.join("commondir")) {
+            Ok(c) => gitdir.join(c.trim()),
+            Err(_) => gitdir.clone(),
+        };
+        info!(
+            "git: workdir `{}`, gitdir `{}`",
+            workdir.display(),
+            gitdir.display()
+        );
+
+        let index_path = gitdir.join("index");
+        let (entries, index_mtime) = match fs::read(&index_path) {
+            Ok(data) => {
+                let md = fs::metadata(&index_path).ok()?;
+                let entries = parse_index(&data)
+                    .map_err(|e| warn!("git: cannot parse index: {e}"))
+                    .ok()?;
+                (entries, (md.mtime(), md.mtime_nsec()))
+            }
+            Err(_) => (Vec::new(), (0, 0)),
+        };
+
+        let mut index = HashMap::new();
+        let mut conflicted = HashSet::new();
+        for e in entries {
+            if e.stage != 0 {
+                conflicted.insert(e.path.clone());
+            } else {
+                index.insert(e.path.clone(), e);
+            }
+        }
+
+        let odb = Odb::open(common.join("objects"));
+        let head = Self::read_head_tree(&odb, &gitdir, &common)
+            .map_err(|e| warn!("git: cannot read HEAD tree: {e}"))
+            .unwrap_or_default();
+
+        let deleted: HashMap<Oid, &String> = head
+            .iter()
+            .filter(|(p, _)| !index.contains_key(*p))
+            .map(|(p, (_, oid))| (*oid, p))
+            .collect();
+        let renamed = index
+            .values()
+            .filter(|e| !head.contains_key(&e.path) && deleted.contains_key(&e.oid))
+            .map(|e| e.path.clone())
+            .collect();
+
+        let mut excludes = Vec::new();
+        let global = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+            .map(|c| c.join("git/ignore"));
+        for file in global.into_iter().chain([common.join("info/exclude")]) {
+            if let Ok(content) = fs::read_to_string(file) {
+                excludes.extend(content.lines().filter_map(|l| Rule::parse("", l)));
+            }
+        }
+
+        Some(Self {
+            workdir,
+            dir,
+            index,
+            index_mtime,
+            conflicted,
+            head,
+            renamed,
+            excludes,
+            gitignores: RefCell::new(HashMap::new()),
+        })
+    }
+
+    fn with_gitignore<T>(&self, base: &str, f: impl FnOnce(&[Rule]) -> T) -> T {
+        let mut cache = self.gitignores.borrow_mut();
+        let rules = cache.entry(base.to_string()).or_insert_with(|| {
+            fs::read_to_string(self.workdir.join(base).join(".gitignore"))
+                .map(|c| c.lines().filter_map(|l| Rule::parse(base, l)).collect())
+                .unwrap_or_default()
+        });
+        f(rules)
+    }
+
+    fn matches_ignore(&self, path: &str, is_dir: bool) -> bool {
+        let mut ignored = None;
+        for rule in &self.excludes {
+            if rule.matches(path, is_dir) {
+                ignored = Some(!rule.negated);
+            }
+        }
+
+        let mut base = String::new();
+        let parents = path.split('/').count() - 1;
+        for (i, comp) in std::iter::once("")
+            .chain(path.split('/'))
+            .take(parents + 1)
+            .enumerate()
+        {
+            if i > 1 {
+                base.push('/');
+            }
+            base.push_str(comp);
+            self.with_gitignore(&base, |rules| {
+                for rule in rules {
+                    if rule.matches(path, is_dir) {
+                        ignored = Some(!rule.negated);
+                    }
+                }
+            });
+        }
+        ignored.unwrap_or(false)
+    }
+
+    fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let comps: Vec<&str> = path.split('/').collect();
+        // a file inside an excluded directory can never be re-included
+        for i in 1..comps.len() {
+            if self.matches_ignore(&comps[..i].join("/"), true) {
+                return true;
+            }
+        }
+        self.matches_ignore(path, is_dir)
+    }
+
+    fn is_tracked_dir(&self, path: &str) -> bool {
+        let prefix = format!("{path}/");
+        self.index.keys().any(|p| p.starts_with(&prefix))
+    }
+
+    fn index_status(&self, e: &IndexEntry) -> char {
+        match self.head.get(&e.path) {
+            None if self.renamed.contains(&e.path) => 'R',
+            None => 'A',
+            Some((mode, oid)) if *oid != e.oid || *mode != e.mode => 'M',
+            Some(_) => '-',
+        }
+    }
+
+    fn worktree_status(&self, e: &IndexEntry) -> char {
+        if e.skip_worktree || e.mode == MODE_GITLINK {
+            return '-';
+        }
+        let path = self.workdir.join(&e.path);
+        let Ok(md) = fs::symlink_metadata(&path) else {
+            return 'D';
+        };
+        if (e.mode == MODE_SYMLINK) != md.file_type().is_symlink() {
+            return 'T';
+        }
+        if e.mode != MODE_SYMLINK && (e.mode & 0o100 != 0) != (md.mode() & 0o100 != 0) {
+            return 'M';
+        }
+
+        let mtime = (md.mtime() as u32, md.mtime_nsec() as u32);
+        let racy = (md.mtime(), md.mtime_nsec()) >= self.index_mtime;
+        if md.size() as u32 == e.size && mtime == e.mtime && !racy {
+            return '-';
+        }
+
+        let data = if e.mode == MODE_SYMLINK {
+            fs::read_link(&path).map(|t| t.as_os_str().as_bytes().to_vec())
+        } else {
+            fs::read(&path)
+        };
+        match data {
+            Ok(data) if hash_blob(&data) == e.oid => '-',
+            _ => 'M',
+        }
+    }
+
+    fn has_untracked(&self, path: &str) -> bool {
+        let Ok(dir) = fs::read_dir(self.workdir.join(path)) else {
+            return false;
+        };
+        for f in dir.flatten() {
+            let name = f.file_name();
+            if name == ".git" {
+                continue;
+            }
+            let child = format!("{path}/{}", name.to_string_lossy());
+            let is_dir = f.file_type().is_ok_and(|t| t.is_dir());
+            if self.is_ignored(&child, is_dir) || self.index.contains_key(&child) {
+                continue;
+            }
+            if !is_dir || self.has_untracked(&child) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn dir_status(&self, path: &str) -> String {
+        if !self.is_tracked_dir(path) {
+            return if self.is_ignored(path, true) {
+                "!!".to_string()
+            } else if self.has_untracked(path) {
+                "??".to_string()
+            } else {
+                "--".to_string()
+            };
+        }
+
+        let prefix = format!("{path}/");
+        let under = |p: &String| p.starts_with(&prefix);
+        let staged = self.conflicted.iter().any(under)
+            || self
+                .head
+                .keys()
+                .any(|p| under(p) && !self.index.contains_key(p))
+            || self
+                .index
+                .values()
+                .any(|e| under(&e.path) && self.index_status(e) != '-');
+        let changed = self
+            .index
+            .values()
+            .any(|e| under(&e.path) && self.worktree_status(e) != '-')
+            || self.has_untracked(path);
+
+        format!(
+            "{}{}",
+            if staged { '*' } else { '-' },
+            if changed { '*' } else { '-' }
+        )
+    }
+
+    /// Two-column `XY` status of `name` inside the listed directory, where `X` is
+    /// the index against `HEAD` and `Y` the working tree against the index.
+    pub fn status(&self, name: &str, is_dir: bool) -> String {
+        let path = if self.dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.dir, name)
+        };
+        if path == ".git" {
+            return "--".to_string();
+        }
+        if self.conflicted.contains(&path) {
+            return "UU".to_string();
+        }
+        if let Some(e) = self.index.get(&path) {
+            return format!("{}{}", self.index_status(e), self.worktree_status(e));
+        }
+        if is_dir {
+            return self.dir_status(&path);
+        }
+        if self.is_ignored(&path, false) {
+            "!!".to_string()
+        } else {
+            "??".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let out = Command::new("git")
+            .args(["-c", "user.name=lss", "-c", "user.email=lss@localhost"])
+            .args(["-c", "init.defaultBranch=master", "-c", "gc.auto=0"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .expect("git is installed");
+        assert!(out.status.success(), "git {args:?}: {:?}", out);
+    }
+
+    /// A repo with one entry for each status: committed `keep`, `modified`
+    /// and `sub/file`, then a staged edit, an unstaged edit, a `git mv`, an
+    /// untracked file and an ignored directory.
+    pub(crate) fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lss-git-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        git(&dir, &["init", "-q"]);
+        for (path, content) in [
+            ("keep", "keep\n"),
+            ("modified", "modified\n"),
+            ("staged", "staged\n"),
+            ("old", "moved\n"),
+            ("sub/file", "file\n"),
+            (".gitignore", "build/\n"),
+        ] {
+            fs::write(dir.join(path), content).unwrap();
+        }
+        git(&dir, &["add", "."]);
+        git(&dir, &["commit", "-q", "-m", "init"]);
+
+        fs::write(dir.join("modified"), "changed\n").unwrap();
+        fs::write(dir.join("staged"), "changed\n").unwrap();
+        fs::write(dir.join("sub/file"), "changed\n").unwrap();
+        git(&dir, &["add", "staged"]);
+        git(&dir, &["mv", "old", "new"]);
+        fs::write(dir.join("untracked"), "untracked\n").unwrap();
+        fs::create_dir(dir.join("build")).unwrap();
+        fs::write(dir.join("build/out"), "out\n").unwrap();
+        dir
+    }
+
+    fn assert_statuses(dir: &Path) {
+        let st = GitStatus::for_dir(dir).expect("inside a work tree");
+        assert_eq!(st.status("keep", false), "--");
+        assert_eq!(st.status("modified", false), "-M");
+        assert_eq!(st.status("staged", false), "M-");
+        assert_eq!(st.status("new", false), "R-");
+        assert_eq!(st.status("untracked", false), "??");
+        assert_eq!(st.status("build", true), "!!");
+        assert_eq!(st.status("sub", true), "-*");
+        assert_eq!(st.status(".gitignore", false), "--");
+        assert_eq!(st.status(".git", true), "--");
+
+        let sub = GitStatus::for_dir(dir.join("sub")).expect("inside a work tree");
+        assert_eq!(sub.status("file", false), "-M");
+    }
+
+    #[test]
+    fn status_with_loose_objects() {
+        let dir = fixture("loose");
+        assert!(
+            !dir.join(".git/objects/pack")
+                .read_dir()
+                .unwrap()
+                .any(|_| true)
+        );
+        assert_statuses(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn status_with_packed_objects() {
+        let dir = fixture("packed");
+        git(&dir, &["gc", "-q"]);
+        assert!(dir.join(".git/packed-refs").exists());
+        let loose = fs::read_dir(dir.join(".git/objects"))
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().len() == 2)
+            .count();
+        assert_eq!(loose, 0);
+        assert_statuses(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_index_is_an_error() {
+        let dir = fixture("truncated");
+        let index = fs::read(dir.join(".git/index")).unwrap();
+        for len in [12, 40, 72, 73, 80] {
+            assert!(parse_index(&index[..len]).is_err(), "length {len}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, env, path::Path, sync::LazyLock};
+
+use crate::FType;
+
+/// When `--icons` puts a glyph in front of names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IconMode {
+    /// Only when stdout is a terminal.
+    Auto,
+    Always,
+    #[default]
+    Never,
+}
+impl<S: AsRef<str>> From<S> for IconMode {
+    fn from(s: S) -> Self {
+        match s.as_ref().to_lowercase().as_str() {
+            "always" | "yes" => Self::Always,
+            "never" | "no" | "none" => Self::Never,
+            _ => Self::Auto,
+        }
+    }
+}
+impl IconMode {
+    pub fn enabled(self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 },
+        }
+    }
+}
+
+/// Env var overriding the built-in table, in the `LS_COLORS` spirit:
+/// `LSS_ICONS="di=:*.rs=:Makefile="`. Keys are a type code (`di`, `fi`,
+/// `ex`, `ln`, `or`, `pi`, `so`, `bd`, `cd`), `*.ext`, or an exact file name.
+pub const ENV: &str = "LSS_ICONS";
+
+static OVERRIDES: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    env::var(ENV)
+        .map(|v| parse_overrides(&v))
+        .unwrap_or_default()
+});
+
+fn parse_overrides(s: &str) -> HashMap<String, String> {
+    s.split(':')
+        .filter_map(|item| {
+            let (key, glyph) = item.split_once('=')?;
+            let key = match key.strip_prefix("*.") {
+                Some(ext) => format!("*.{}", ext.to_lowercase()),
+                None => key.to_string(),
+            };
+            Some((key, glyph.to_string()))
+        })
+        .collect()
+}
+
+fn get_type_code(ftype: &FType) -> &'static str {
+    match ftype {
+        FType::File(true) => "ex",
+        FType::File(false) => "fi",
+        FType::Dir => "di",
+        FType::Symlink(_) => "ln",
+        FType::BrokenSymlink => "or",
+        FType::Fifo => "pi",
+        FType::Socket => "so",
+        FType::BlockDevice => "bd",
+        FType::CharDevice => "cd",
+        FType::Other => "",
+    }
+}
+
+fn get_type_icon(ftype: &FType) -> &'static str {
+    match ftype {
+        FType::File(true) => "\u{f489}",
+        FType::File(false) => "\u{f15b}",
+        FType::Dir => "\u{f07b}",
+        FType::Symlink(_) => "\u{f0c1}",
+        FType::BrokenSymlink => "\u{f127}",
+        FType::Fifo => "\u{f0ec}",
+        FType::Socket => "\u{f1e6}",
+        FType::BlockDevice => "\u{f0a0}",
+        FType::CharDevice => "\u{f11c}",
+        FType::Other => "\u{f128}",
+    }
+}
+
+fn get_name_icon(name: &str, ftype: &FType) -> Option<&'static str> {
+    let icon = match (name, ftype) {
+        (".git", FType::Dir) => "\u{e5fb}",
+        (_, FType::Dir) => return None,
+        ("Cargo.toml" | "Cargo.lock", _) => "\u{e7a8}",
+        ("Makefile" | "makefile" | "GNUmakefile" | "CMakeLists.txt", _) => "\u{f0ad}",
+        (".gitignore" | ".gitattributes" | ".gitmodules" | ".gitconfig", _) => "\u{f1d3}",
+        ("Dockerfile" | "Containerfile", _) => "\u{f308}",
+        ("LICENSE" | "LICENSE.md" | "COPYING", _) => "\u{f0e3}",
+        _ => return None,
+    };
+    Some(icon)
+}
+
+fn get_ext_icon(ext: &str) -> Option<&'static str> {
+    let icon = match ext {
+        "rs" => "\u{e7a8}",
+        "c" => "\u{e61e}",
+        "h" | "hpp" => "\u{f0fd}",
+        "cpp" | "cc" | "cxx" => "\u{e61d}",
+        "go" => "\u{e627}",
+        "py" => "\u{e606}",
+        "js" | "mjs" => "\u{e74e}",
+        "ts" => "\u{e628}",
+        "java" => "\u{e738}",
+        "sh" | "bash" | "zsh" | "fish" => "\u{f489}",
+        "md" | "markdown" => "\u{f48a}",
+        "json" => "\u{e60b}",
+        "toml" | "ini" | "conf" | "cfg" => "\u{e615}",
+        "yml" | "yaml" => "\u{e615}",
+        "html" | "htm" => "\u{f13b}",
+        "css" => "\u{e749}",
+        "lock" => "\u{f023}",
+        "txt" | "log" => "\u{f15c}",
+        "pdf" => "\u{f1c1}",
+        "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "bmp" | "ico" => "\u{f1c5}",
+        "mp3" | "flac" | "wav" | "ogg" | "opus" => "\u{f001}",
+        "mp4" | "mkv" | "webm" | "avi" | "mov" => "\u{f03d}",
+        "zip" | "gz" | "tgz" | "tar" | "xz" | "zst" | "bz2" | "7z" | "rar" => "\u{f410}",
+        _ => return None,
+    };
+    Some(icon)
+}
+
+/// Glyph for an entry: exact file name first, then extension, then type,
+/// each checked against the `LSS_ICONS` overrides before the built-ins.
+/// Directories and special files only take name and type icons.
+pub fn get_icon(name: &str, ftype: &FType) -> String {
+    let overrides = &*OVERRIDES;
+    if let Some(icon) = overrides.get(name) {
+        return icon.clone();
+    }
+    if let Some(icon) = get_name_icon(name, ftype) {
+        return icon.to_string();
+    }
+    if matches!(ftype, FType::File(_) | FType::Symlink(_))
+        && let Some(ext) = Path::new(name).extension().and_then(|e| e.to_str())
+    {
+        let ext = ext.to_lowercase();
+        if let Some(icon) = overrides.get(&format!("*.{ext}")) {
+            return icon.clone();
+        }
+        if let Some(icon) = get_ext_icon(&ext) {
+            return icon.to_string();
+        }
+    }
+    overrides
+        .get(get_type_code(ftype))
+        .cloned()
+        .unwrap_or_else(|| get_type_icon(ftype).to_string())
+}
@@ -0,0 +1,135 @@
+use std::{collections::HashMap, fmt, fs, io};
+
+/// One line of `/proc/locks`.
+#[derive(Debug, Clone)]
+pub struct Lock {
+    /// POSIX, FLOCK, OFD, LEASE, ...
+    pub kind: String,
+    /// READ, WRITE (or UNLCK for a lease being broken)
+    pub mode: String,
+    pub start: u64,
+    /// Last byte, `None` for locks up to EOF.
+    pub end: Option<u64>,
+    /// Owning process; OFD locks belong to an open file, not a process.
+    pub pid: Option<u32>,
+    /// Waiting for the lock rather than holding it.
+    pub blocked: bool,
+}
+
+impl fmt::Display for Lock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.blocked {
+            write!(f, "-> ")?;
+        }
+        write!(f, "{} {} {}-", self.kind, self.mode, self.start)?;
+        match self.end {
+            Some(end) => write!(f, "{end}")?,
+            None => write!(f, "EOF")?,
+        }
+        match self.pid {
+            Some(pid) => write!(f, " {pid}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `1: POSIX  ADVISORY  WRITE 1234 08:01:1234567 0 EOF`, with `->` after the
+/// id for blocked requests. Returns the lock and its `(dev, ino)`.
+fn parse_line(line: &str) -> Option<((u64, u64), Lock)> {
+    let mut cols = line.split_whitespace().skip(1).peekable();
+    let blocked = cols.next_if_eq(&"->").is_some();
+    let kind = match cols.next()? {
+        "OFDLCK" => "OFD",
+        kind => kind,
+    };
+    let _advisory = cols.next()?;
+    let mode = cols.next()?;
+    let pid = cols.next()?.parse::<i64>().ok()?;
+
+    let mut id = cols.next()?.split(':');
+    let major = u32::from_str_radix(id.next()?, 16).ok()?;
+    let minor = u32::from_str_radix(id.next()?, 16).ok()?;
+    let ino = id.next()?.parse().ok()?;
+
+    let start = cols.next()?.parse().ok()?;
+    let end = match cols.next()? {
+        "EOF" => None,
+        end => Some(end.parse().ok()?),
+    };
+
+    let lock = Lock {
+        kind: kind.to_string(),
+        mode: mode.to_string(),
+        start,
+        end,
+        pid: u32::try_from(pid).ok().filter(|&p| p > 0),
+        blocked,
+    };
+    Some(((libc::makedev(major, minor), ino), lock))
+}
+
+/// All locks held or waited for on the system, by `(dev, ino)`.
+pub fn read_locks() -> io::Result<HashMap<(u64, u64), Vec<Lock>>> {
+    let mut res: HashMap<_, Vec<_>> = HashMap::new();
+    for (key, lock) in fs::read_to_string("/proc/locks")?
+        .lines()
+        .filter_map(parse_line)
+    {
+        res.entry(key).or_default().push(lock);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posix_lock() {
+        let (key, lock) = parse_line("1: POSIX  ADVISORY  WRITE 1234 08:01:1234567 0 EOF").unwrap();
+        assert_eq!(key, (libc::makedev(8, 1), 1234567));
+        assert_eq!((lock.kind.as_str(), lock.mode.as_str()), ("POSIX", "WRITE"));
+        assert_eq!((lock.start, lock.end, lock.pid), (0, None, Some(1234)));
+        assert!(!lock.blocked);
+        assert_eq!(lock.to_string(), "POSIX WRITE 0-EOF 1234");
+    }
+
+    #[test]
+    fn hex_device_numbers() {
+        let (key, lock) = parse_line("2: FLOCK  ADVISORY  READ 99 fd:1a:42 0 EOF").unwrap();
+        assert_eq!(key, (libc::makedev(0xfd, 0x1a), 42));
+        assert_eq!(lock.to_string(), "FLOCK READ 0-EOF 99");
+    }
+
+    #[test]
+    fn blocked_request() {
+        let (_, lock) =
+            parse_line("1: -> POSIX  ADVISORY  WRITE 1235 08:01:1234567 100 199").unwrap();
+        assert!(lock.blocked);
+        assert_eq!(lock.end, Some(199));
+        assert_eq!(lock.to_string(), "-> POSIX WRITE 100-199 1235");
+    }
+
+    #[test]
+    fn ofd_lock_has_no_pid() {
+        let (_, lock) = parse_line("3: OFDLCK ADVISORY  READ -1 00:2e:100 10 19").unwrap();
+        assert_eq!(lock.kind, "OFD");
+        assert_eq!(lock.pid, None);
+        assert_eq!(lock.to_string(), "OFD READ 10-19");
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "",
+            "1:",
+            "1: POSIX  ADVISORY  WRITE 1234",
+            "1: POSIX  ADVISORY  WRITE 1234 08:01 0 EOF",
+            "1: POSIX  ADVISORY  WRITE 1234 zz:01:5 0 EOF",
+            "1: POSIX  ADVISORY  WRITE x 08:01:5 0 EOF",
+            "1: POSIX  ADVISORY  WRITE 1234 08:01:5 0 end",
+        ] {
+            assert!(parse_line(line).is_none(), "{line:?}");
+        }
+    }
+}
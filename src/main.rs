@@ -3,13 +3,51 @@ use anyhow::anyhow;
 use clap::Parser;
 use colored::Colorize;
 use log::{error, info, warn};
+use unicode_width::UnicodeWidthChar;
+
+mod access;
+mod acl;
+mod alloc;
+mod caps;
+mod explain;
+mod extents;
+mod filter;
+mod flags;
+mod git;
+mod icons;
+mod locks;
+mod mounts;
+mod pool;
+mod procs;
+mod query;
+mod stat;
+mod theme;
+mod xattr;
+
+use access::Creds;
+use acl::Acl;
+use alloc::AllocSort;
+use caps::Caps;
+use extents::Extents;
+use filter::{Filter, TypeFilter};
+use git::GitStatus;
+use icons::IconMode;
+use locks::Lock;
+use mounts::MOUNTS;
+use procs::OpenFiles;
+use query::Query;
+use stat::Stat;
+use theme::ThemePart;
+use xattr::Xattr;
 
 use std::{
+    collections::{HashMap, HashSet},
     fs::{self, Metadata},
     io, mem,
-    os::unix::fs::{MetadataExt, PermissionsExt},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
     path::{Path, PathBuf},
-    time::{Duration, SystemTime},
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct Time {
@@ -30,14 +68,22 @@ impl From<SystemTime> for Time {
 }
 
 impl Time {
-    pub fn from_created(metadata: &Metadata) -> io::Result<Self> {
-        let created = metadata.created()?;
-        Ok(Self::from(created))
-    }
     pub fn from_modified(metadata: &Metadata) -> io::Result<Self> {
         let modified = metadata.modified()?;
         Ok(Self::from(modified))
     }
+    pub fn from_calendar(year: i32, month: u32, day: u32, hour: u32, min: u32, sec: u32) -> Self {
+        let y = if month <= 2 { year - 1 } else { year } as i64;
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        let local = days * 86400 + (hour * 3600 + min * 60 + sec) as i64;
+        let utc = local - Self::get_local_timezone_offset(local);
+        Self::from(SystemTime::UNIX_EPOCH + Duration::from_secs(utc.max(0) as u64))
+    }
     fn get_local_timezone_offset(duration_since_epoch: i64) -> i64 {
         use libc::{localtime_r, time_t, tm};
 
@@ -201,6 +247,15 @@ struct LssConf {
     width: Option<usize>,
     #[clap(short = 'H', long)]
     humanize: bool,
+    /// Print human-readable sizes in powers of 1000 (k, M, G)
+    #[clap(long)]
+    si: bool,
+    /// Print human-readable sizes with IEC suffixes (KiB, MiB, GiB)
+    #[clap(long)]
+    iec: bool,
+    /// Scale sizes by SIZE before printing them (e.g. M, 1K, MB, '1)
+    #[clap(long, value_parser = parse_block_size)]
+    block_size: Option<BlockSize>,
     #[clap(short = 'Q', long)]
     quoted: bool,
     #[clap(short = 'L', long)]
@@ -214,22 +269,167 @@ struct LssConf {
     all: bool,
     #[clap(short, long)]
     long: bool,
+    /// Like -l, but list numeric user and group ids
+    #[clap(short = 'n', long = "numeric-uid-gid")]
+    numeric_ids: bool,
+    /// Like -l, but do not list the owner
+    #[clap(short = 'g')]
+    no_owner: bool,
+    /// Do not list the group in long mode
+    #[clap(short = 'G', long)]
+    no_group: bool,
+    /// List the author of each entry in long mode
+    #[clap(long)]
+    author: bool,
+    /// Show the birth time of each entry in long mode
+    #[clap(long)]
+    btime: bool,
+    /// List extended attributes under each entry in long mode
+    #[clap(short = '@', long)]
+    xattr: bool,
+    /// Print POSIX ACL entries under each entry in long mode, like getfacl
+    #[clap(long)]
+    acl: bool,
+    /// Show the permission bits in octal next to the mode string
+    #[clap(long)]
+    octal: bool,
+    /// Explain the permission bits of PATH in plain English and exit
+    #[clap(long, value_name = "PATH")]
+    explain_mode: Option<PathBuf>,
+    /// Show the effective rwx access of the invoking user in long mode
+    #[clap(long)]
+    access: bool,
+    /// Show the rwx access another user would get, from mode bits, groups
+    /// and ACLs, in long mode
+    #[clap(long, value_name = "NAME", value_parser = access::parse_user)]
+    as_user: Option<Creds>,
+    /// Show the SELinux security context of each entry
+    #[clap(short = 'Z', long)]
+    context: bool,
+    /// Show the file capabilities of each entry in long mode
+    #[clap(long)]
+    caps: bool,
+    /// Show inode flags in long mode, like lsattr
+    #[clap(long)]
+    attrs: bool,
+    /// Show statx attributes in long mode (immutable, append, compressed,
+    /// encrypted, verity, dax)
+    #[clap(long)]
+    statx_attrs: bool,
 
     #[clap(short, long)]
     blocks: bool,
+    /// Print the inode number of each entry
+    #[clap(short, long)]
+    inode: bool,
+    /// Show the device (major:minor) each entry lives on in long mode
+    #[clap(long)]
+    dev: bool,
+    /// Show the filesystem type and source each entry lives on in long mode
+    #[clap(long)]
+    fs: bool,
+    /// Tag entries that are hardlinks of the same inode and summarize the
+    /// space they actually use
+    #[clap(long)]
+    hardlinks: bool,
+    /// Show allocated size as a percentage of the apparent size, marking
+    /// sparse (S) and preallocated (P) files
+    #[clap(long)]
+    alloc: bool,
+    /// With --alloc, also count data segments and holes of regular files
+    #[clap(long)]
+    alloc_extents: bool,
+    /// Show the extents of regular files: count and shared, inline and
+    /// unwritten bytes, with a summary of what reflinks save
+    #[clap(long)]
+    extents: bool,
     #[clap(short = 'S', long = "size")]
     size_sort: bool,
+    /// Sort by space saved by holes or wasted by preallocation
+    #[clap(long, value_name = "saved|wasted")]
+    alloc_sort: Option<AllocSort>,
+    /// Show recursive (du-style) sizes for directories, as apparent/allocated
+    /// in the size column; -S then sorts by the apparent size
+    #[clap(long)]
+    total_size: bool,
+    /// Do not descend into other filesystems in recursive modes
+    #[clap(short = 'x', long)]
+    one_file_system: bool,
     #[clap(short, long)]
     reverse: bool,
 
     #[clap(long, default_value = "standart")]
     color: DisplayColor,
+    /// Colour long-format columns too: perms, size, age, owner or all
+    #[clap(long, value_delimiter = ',')]
+    theme: Vec<ThemePart>,
+    /// Nerd Font glyphs before names: auto, always or never (see LSS_ICONS)
+    #[clap(long, default_value = "never", default_missing_value = "auto", num_args = 0..=1, require_equals = true)]
+    icons: IconMode,
+    /// Number of threads collecting entry metadata (defaults to the CPU count)
+    #[clap(long)]
+    threads: Option<usize>,
+
+    /// Show which processes have each entry open or mapped
+    #[clap(long)]
+    open_by: bool,
+    /// Show the locks held or waited for on each entry in long mode
+    #[clap(long)]
+    locks: bool,
+
+    /// Show the git status of each entry in long mode
+    #[clap(long)]
+    git: bool,
+
+    /// Only list entries of the given types (f,d,l,p,s,b,c)
+    #[clap(long = "type", value_delimiter = ',')]
+    types: Vec<TypeFilter>,
+    /// Only list entries of at least this size (e.g. 10K, 1.5M)
+    #[clap(long, value_parser = filter::parse_size)]
+    min_size: Option<u64>,
+    /// Only list entries of at most this size
+    #[clap(long, value_parser = filter::parse_size)]
+    max_size: Option<u64>,
+    /// Only list entries modified after a duration ago (7d, 3h), a date or a file
+    #[clap(long, value_parser = filter::parse_time)]
+    newer: Option<Time>,
+    /// Only list entries modified before a duration ago (7d, 3h), a date or a file
+    #[clap(long, value_parser = filter::parse_time)]
+    older: Option<Time>,
+    /// Only list entries someone holds or waits for a lock on
+    #[clap(long)]
+    locked: bool,
+    /// Only list entries matching an expression, e.g. `size > 10M and ext in (log, gz)`;
+    /// times compare as timestamps, so `mtime > 2d` means newer than two days
+    #[clap(long = "where", value_parser = Query::parse)]
+    query: Option<Query>,
 }
+impl LssConf {
+    fn is_long(&self) -> bool {
+        self.long || self.numeric_ids || self.no_owner || self.xattr || self.acl
+    }
+    /// Whether names will actually be coloured, which `colored` decides from
+    /// the tty and `NO_COLOR`/`CLICOLOR_FORCE`.
+    fn colorized(&self) -> bool {
+        matches!(self.color, DisplayColor::Standart)
+            && colored::control::SHOULD_COLORIZE.should_colorize()
+    }
+    /// Whether the `--theme` colours `part`, which it never does without colour.
+    fn themed(&self, part: ThemePart) -> bool {
+        matches!(self.color, DisplayColor::Standart)
+            && self.theme.iter().any(|&p| p == part || p == ThemePart::All)
+    }
+}
+
 enum FType {
     File(bool),
     Dir,
     Symlink(String),
     BrokenSymlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
     Other,
 }
 
@@ -238,23 +438,75 @@ struct FEntry {
     path: PathBuf,
     ftype: FType,
     modified: Time,
+    /// Birth time, if the filesystem records one.
+    created: Option<Time>,
 
+    /// Allocated 512-byte blocks, as reported by `st_blocks`.
     nblocks: u64,
+    hblocks: String,
+    blksize: u64,
+    /// Data segments and holes, with `--alloc-extents`.
+    segments: Option<(u64, u64)>,
+    halloc: String,
+    hsegments: String,
+    extents: Option<Extents>,
+    hextents: String,
     size: u64,
     hsize: String,
+    nlink: u64,
+    /// `STATX_*` fields the filesystem supplied; the others render as `-`.
+    mask: u32,
+    ino: u64,
+    dev: u64,
+    /// Something is mounted here.
+    mount_point: bool,
+    /// Filesystem type and source, with `--fs`.
+    fs: Option<(String, String)>,
+    uid: u32,
+    gid: u32,
     owner: String,
     group: String,
     mode: String,
+    octal: String,
+    /// Effective access of the invoking user, with `--access`.
+    access: String,
+    /// Access of the `--as-user` user.
+    user_access: String,
+    attributes: String,
+    /// `FS_IOC_GETFLAGS` inode flags, where the filesystem has them.
+    flags: Option<u32>,
+    xattrs: Vec<Xattr>,
+    acl: Option<Acl>,
+    default_acl: Option<Acl>,
+    /// SELinux context, `None` where the filesystem has no labels.
+    context: Option<String>,
+    /// File capabilities in `getcap` form.
+    caps: Option<String>,
+    /// `comm[pid]` of the processes holding the entry, with `--open-by`.
+    open_by: Option<String>,
+    /// Entries of `/proc/locks` for this inode.
+    locks: Vec<Lock>,
+    /// Id shared by the listed entries that are hardlinks of one inode.
+    link_group: Option<usize>,
+    git: Option<String>,
 }
 
 impl FEntry {
     fn _get_name_and_suffix(&self) -> (String, Option<char>) {
+        if self.mount_point {
+            return (self.name.blue().bold().underline().to_string(), Some('^'));
+        }
         match self.ftype {
+            // LS_COLORS `ca=30;41`
+            FType::File(_) if self.caps.is_some() => (self.name.black().on_red().to_string(), None),
             FType::File(true) => (self.name.green().to_string(), None),
             FType::File(false) | FType::Other => (self.name.white().to_string(), None),
             FType::Dir => (self.name.blue().to_string(), Some('/')),
             FType::Symlink(_) => (self.name.cyan().to_string(), Some('@')),
             FType::BrokenSymlink => (self.name.red().to_string(), Some('!')),
+            FType::Fifo => (self.name.yellow().to_string(), Some('|')),
+            FType::Socket => (self.name.magenta().to_string(), Some('=')),
+            FType::BlockDevice | FType::CharDevice => (self.name.yellow().bold().to_string(), None),
         }
     }
     fn get_styled_name(&self, suf: bool) -> String {
@@ -278,27 +530,49 @@ impl FEntry {
             self.name.clone()
         }
     }
-    fn to_fixed_str(
-        &self,
-        is_human: bool,
-        maxs: &Maxs,
-        blocks: bool,
-        color: DisplayColor,
-        quoted: bool,
-        link: bool,
-    ) -> String {
-        let (size, len) = if is_human {
-            (self.hsize.clone(), maxs.hsize)
+    fn has(&self, field: u32) -> bool {
+        self.mask & field != 0
+    }
+    fn get_ino(&self) -> String {
+        match self.has(libc::STATX_INO) {
+            true => self.ino.to_string(),
+            false => "-".to_string(),
+        }
+    }
+    fn get_nlink(&self) -> String {
+        match self.has(libc::STATX_NLINK) {
+            true => self.nlink.to_string(),
+            false => "-".to_string(),
+        }
+    }
+    /// Owner as rendered: the uid with `-n`, unless statx left it out.
+    fn get_owner(&self, conf: &LssConf) -> String {
+        match conf.numeric_ids && self.owner != "-" {
+            true => self.uid.to_string(),
+            false => self.owner.clone(),
+        }
+    }
+    fn get_group(&self, conf: &LssConf) -> String {
+        match conf.numeric_ids && self.group != "-" {
+            true => self.gid.to_string(),
+            false => self.group.clone(),
+        }
+    }
+    fn with_icon(&self, name: String, conf: &LssConf) -> String {
+        if conf.icons.enabled() {
+            format!("{} {}", icons::get_icon(&self.name, &self.ftype), name)
         } else {
-            (self.size.to_string(), maxs.size)
-        };
+            name
+        }
+    }
+    fn to_fixed_str(&self, conf: &LssConf, maxs: &Maxs) -> String {
         let name = if let FType::Symlink(target) = &self.ftype
-            && link
+            && conf.link
         {
-            if quoted {
+            if conf.quoted {
                 format!("\"{}\" -> \"{}\"", &self.name, &target)
             } else {
-                match color {
+                match conf.color {
                     DisplayColor::Standart => {
                         format!("{} -> {}", self.get_styled_name(false), target)
                     }
@@ -307,46 +581,159 @@ impl FEntry {
                     }
                 }
             }
+        } else if conf.quoted {
+            format!("\"{}\"", &self.name)
         } else {
-            if quoted {
-                format!("\"{}\"", &self.name)
-            } else {
-                match color {
-                    DisplayColor::Standart => self.get_styled_name(true),
-                    DisplayColor::Empty => self.get_colorless_name(true),
-                }
+            match conf.color {
+                DisplayColor::Standart => self.get_styled_name(true),
+                DisplayColor::Empty => self.get_colorless_name(true),
             }
         };
+        let name = self.with_icon(name, conf);
 
-        if blocks {
-            format!(
-                "{blocks:>bll$} {mode} {owner:>ownl$} {group:>grpl$} {size:>szl$} {modified} {name}",
-                blocks = self.nblocks,
-                bll = maxs.blocks,
-                mode = self.mode,
-                owner = self.owner,
-                ownl = maxs.owner,
-                group = self.group,
-                grpl = maxs.group,
-                size = size,
-                szl = len,
-                modified = self.modified.format(),
-                name = name,
-            )
+        let mut cols = Vec::new();
+        if conf.inode {
+            cols.push(format!("{:>inl$}", self.get_ino(), inl = maxs.inode));
+        }
+        if conf.blocks {
+            cols.push(format!("{:>bll$}", self.hblocks, bll = maxs.blocks));
+        }
+        let mode = format!("{:<model$}", self.mode, model = maxs.mode);
+        cols.push(match conf.themed(ThemePart::Perms) {
+            true => theme::style_mode(&mode),
+            false => mode,
+        });
+        if conf.octal {
+            cols.push(self.octal.clone());
+        }
+        if conf.access {
+            cols.push(self.access.clone());
+        }
+        if conf.as_user.is_some() {
+            cols.push(self.user_access.clone());
+        }
+        cols.push(format!("{:>nll$}", self.get_nlink(), nll = maxs.nlink));
+        let owner = format!("{:>ownl$}", self.get_owner(conf), ownl = maxs.owner);
+        let owner = match conf.themed(ThemePart::Owner) {
+            true => theme::style_owner(&owner, self.uid).to_string(),
+            false => owner,
+        };
+        if !conf.no_owner {
+            cols.push(owner.clone());
+        }
+        if !conf.no_group {
+            cols.push(format!(
+                "{:>grpl$}",
+                self.get_group(conf),
+                grpl = maxs.group
+            ));
+        }
+        if conf.author {
+            // Linux has no separate author field, so like GNU ls this is the owner
+            cols.push(owner);
+        }
+        if conf.context {
+            let context = self.context.as_deref().unwrap_or("?");
+            cols.push(format!("{:<ctxl$}", context, ctxl = maxs.context));
+        }
+        if conf.caps {
+            let caps = self.caps.as_deref().unwrap_or("-");
+            cols.push(format!("{:<capl$}", caps, capl = maxs.caps));
+        }
+        if conf.dev {
+            cols.push(format!("{:>devl$}", get_device(self.dev), devl = maxs.dev));
+        }
+        if conf.fs {
+            let (fstype, source) = self.fs.clone().unwrap_or(("?".into(), "?".into()));
+            cols.push(format!("{:<fstl$}", fstype, fstl = maxs.fstype));
+            cols.push(format!("{:<srcl$}", source, srcl = maxs.source));
+        }
+        let hsize = format!("{:>szl$}", self.hsize, szl = maxs.hsize);
+        cols.push(match conf.themed(ThemePart::Size) {
+            true => theme::style_size(&hsize, self.size).to_string(),
+            false => hsize,
+        });
+        if conf.alloc {
+            cols.push(format!("{:>all$}", self.halloc, all = maxs.alloc));
+        }
+        if conf.alloc && conf.alloc_extents {
+            cols.push(format!("{:>segl$}", self.hsegments, segl = maxs.segments));
+        }
+        if conf.extents {
+            cols.push(format!("{:<extl$}", self.hextents, extl = maxs.extents));
+        }
+        if self.has(libc::STATX_MTIME) {
+            let modified = self.modified.format();
+            cols.push(match conf.themed(ThemePart::Age) {
+                true => theme::style_age(&modified, &self.modified).to_string(),
+                false => modified,
+            });
         } else {
-            format!(
-                "{mode} {owner:>ownl$} {group:>grpl$} {size:>szl$} {modified} {name}",
-                mode = self.mode,
-                owner = self.owner,
-                ownl = maxs.owner,
-                group = self.group,
-                grpl = maxs.group,
-                size = size,
-                szl = len,
-                modified = self.modified.format(),
-                name = name,
-            )
+            cols.push(format!("{:>12}", "-"));
+        }
+        if conf.btime {
+            let created = self.created.as_ref().map_or("-".to_string(), Time::format);
+            let created = format!("{created:>12}");
+            cols.push(match &self.created {
+                Some(time) if conf.themed(ThemePart::Age) => {
+                    theme::style_age(&created, time).to_string()
+                }
+                _ => created,
+            });
+        }
+        if conf.statx_attrs {
+            cols.push(self.attributes.clone());
+        }
+        if conf.attrs {
+            let flags = self.flags.map_or("?".to_string(), flags::to_lsattr);
+            cols.push(format!("{:<fll$}", flags, fll = flags::WIDTH));
+        }
+        if conf.hardlinks && maxs.link_group > 0 {
+            cols.push(match self.link_group {
+                Some(id) => get_styled_link_group(id, maxs.link_group, conf.color),
+                None => " ".repeat(maxs.link_group),
+            });
+        }
+        if let Some(git) = &self.git
+            && conf.git
+        {
+            cols.push(get_styled_git_status(git, conf.color));
+        }
+        if conf.locks {
+            cols.push(format!(
+                "{:<lkl$}",
+                get_locks_str(&self.locks),
+                lkl = maxs.locks
+            ));
+        }
+        if let Some(open_by) = &self.open_by {
+            cols.push(format!("{:<opl$}", open_by, opl = maxs.open_by));
+        }
+        cols.push(name);
+
+        let mut line = cols.join(" ");
+        if conf.acl {
+            let user = |uid: u32| match conf.numeric_ids {
+                true => uid.to_string(),
+                false => get_username(uid).unwrap_or_else(|_| uid.to_string()),
+            };
+            let group = |gid: u32| match conf.numeric_ids {
+                true => gid.to_string(),
+                false => get_groupname(gid).unwrap_or_else(|_| gid.to_string()),
+            };
+            let acls = [(&self.acl, ""), (&self.default_acl, "default:")];
+            for (acl, prefix) in acls.iter().filter_map(|(a, p)| Some((a.as_ref()?, p))) {
+                for entry in acl.to_lines(prefix, user, group) {
+                    line.push_str(&format!("\n    {entry}"));
+                }
+            }
+        }
+        if conf.xattr {
+            for attr in &self.xattrs {
+                line.push_str(&format!("\n    {}: {}", attr.name, attr.describe()));
+            }
         }
+        line
     }
     fn to_abs_str(&self, quoted: bool) -> Result<String> {
         let absp = fs::canonicalize(&self.path)?;
@@ -356,33 +743,54 @@ impl FEntry {
             Ok(absp.display().to_string())
         }
     }
-    fn to_str(&self, color: DisplayColor, quoted: bool) -> String {
-        if quoted {
+    fn to_str(&self, conf: &LssConf, maxs: &Maxs) -> String {
+        let name = if conf.quoted {
             format!("\"{}\"", &self.name)
         } else {
-            match color {
+            match conf.color {
                 DisplayColor::Standart => self.get_styled_name(true),
                 DisplayColor::Empty => self.get_colorless_name(true),
             }
+        };
+        let name = self.with_icon(name, conf);
+        let mut cols = Vec::new();
+        if conf.inode {
+            cols.push(format!("{:>inl$}", self.get_ino(), inl = maxs.inode));
+        }
+        if conf.context {
+            let context = self.context.as_deref().unwrap_or("?");
+            cols.push(format!("{:<ctxl$}", context, ctxl = maxs.context));
         }
+        if conf.hardlinks && maxs.link_group > 0 {
+            cols.push(match self.link_group {
+                Some(id) => get_styled_link_group(id, maxs.link_group, conf.color),
+                None => " ".repeat(maxs.link_group),
+            });
+        }
+        cols.push(name);
+        cols.join(" ")
     }
 }
 
-fn get_human_readable_size(size: u64) -> String {
+fn get_human_readable_size(size: u64, base: u64, iec: bool) -> String {
     let mut size = size as f64;
     let mut suffix = "B";
-    if size > 1024. {
-        size /= 1024.;
-        suffix = "K";
-    }
-    if size > 1024. {
-        size /= 1024.;
-        suffix = "M";
-    }
-    if size > 1024. {
-        size /= 1024.;
-        suffix = "G";
+    for unit in ["K", "M", "G", "T", "P", "E"] {
+        if size <= base as f64 {
+            break;
+        }
+        size /= base as f64;
+        suffix = if unit == "K" && base == 1000 {
+            "k"
+        } else {
+            unit
+        };
     }
+    let suffix = if iec && suffix != "B" {
+        format!("{suffix}iB")
+    } else {
+        suffix.to_string()
+    };
 
     let rounded = (size * 100.).round() / 100.;
 
@@ -395,71 +803,298 @@ fn get_human_readable_size(size: u64) -> String {
     }
 }
 
-fn get_username(uid: u32) -> Result<String> {
-    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
-    let mut buf = vec![0u8; 1024];
-    let mut res: *mut libc::passwd = std::ptr::null_mut();
-
-    let ret = unsafe {
-        libc::getpwuid_r(
-            uid,
-            &mut passwd,
-            buf.as_mut_ptr() as *mut _,
-            buf.len(),
-            &mut res,
-        )
+/// Multiplier for a GNU size suffix: `K`/`KiB` are powers of 1024, `KB` of 1000.
+fn get_unit_multiplier(unit: &str) -> Option<u64> {
+    let mut chars = unit.chars();
+    let Some(prefix) = chars.next() else {
+        return Some(1);
     };
+    if unit == "B" {
+        return Some(1);
+    }
+    let exp = "KMGTPE".find(prefix.to_ascii_uppercase())? as u32 + 1;
+    match chars.as_str() {
+        "" | "iB" => Some(1024u64.pow(exp)),
+        "B" => Some(1000u64.pow(exp)),
+        _ => None,
+    }
+}
 
-    if ret == 0 && !res.is_null() {
-        Ok(unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) }
-            .to_str()
-            .map(|s| s.to_string())?)
-    } else {
-        Err(anyhow!("get username error"))
+#[derive(Debug, Clone)]
+struct BlockSize {
+    size: u64,
+    suffix: String,
+    group: bool,
+}
+
+impl BlockSize {
+    fn new(size: u64) -> Self {
+        Self {
+            size,
+            suffix: String::new(),
+            group: false,
+        }
     }
 }
 
-fn get_groupname(gid: u32) -> Result<String> {
-    let mut group: libc::group = unsafe { std::mem::zeroed() };
-    let mut buf = vec![0u8; 1024];
-    let mut res: *mut libc::group = std::ptr::null_mut();
-
-    let ret = unsafe {
-        libc::getgrgid_r(
-            gid,
-            &mut group,
-            buf.as_mut_ptr() as *mut _,
-            buf.len(),
-            &mut res,
-        )
+/// Parses a `--block-size` spec the way GNU ls does: `1K`, `M`, `MB`, `KiB`, `'1`.
+/// A spec without a leading number also prints its unit after each size.
+fn parse_block_size(s: &str) -> Result<BlockSize, String> {
+    let (group, spec) = match s.strip_prefix('\'') {
+        Some(spec) => (true, spec),
+        None => (false, s),
+    };
+    if spec.is_empty() {
+        return Err(format!("invalid block size `{s}`"));
+    }
+    let split = spec
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(spec.len());
+    let (num, unit) = spec.split_at(split);
+    let mult = get_unit_multiplier(unit).ok_or(format!("invalid block size `{s}`"))?;
+    let num: u64 = if num.is_empty() {
+        1
+    } else {
+        num.parse()
+            .map_err(|_| format!("invalid block size `{s}`"))?
     };
+    let size = num
+        .checked_mul(mult)
+        .filter(|&n| n > 0)
+        .ok_or(format!("invalid block size `{s}`"))?;
+
+    Ok(BlockSize {
+        size,
+        suffix: if split == 0 {
+            unit.to_string()
+        } else {
+            String::new()
+        },
+        group,
+    })
+}
+
+/// Thousands separator of `LC_NUMERIC`, read once after `main` has called
+/// `setlocale`. Empty in the C locale, where GNU ls does not group either.
+static THOUSANDS_SEP: LazyLock<String> = LazyLock::new(|| unsafe {
+    let lc = libc::localeconv();
+    if lc.is_null() || (*lc).thousands_sep.is_null() {
+        return String::new();
+    }
+    std::ffi::CStr::from_ptr((*lc).thousands_sep)
+        .to_string_lossy()
+        .into_owned()
+});
+
+fn group_digits(n: u64, sep: &str) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push_str(sep);
+        }
+        out.push(c);
+    }
+    out
+}
 
-    if ret == 0 && !res.is_null() {
-        Ok(unsafe { std::ffi::CStr::from_ptr(group.gr_name) }
-            .to_str()
-            .map(|s| s.to_owned())?)
+/// Formats a byte count for display, honouring `-H`, `--si`, `--iec` and `--block-size`.
+/// `default` is the block size used when none was given on the command line.
+fn format_size(bytes: u64, conf: &LssConf, default: u64) -> String {
+    if conf.si {
+        return get_human_readable_size(bytes, 1000, false);
+    }
+    if conf.humanize || conf.iec {
+        return get_human_readable_size(bytes, 1024, conf.iec);
+    }
+
+    let bs = conf
+        .block_size
+        .clone()
+        .unwrap_or_else(|| BlockSize::new(default));
+    let n = bytes.div_ceil(bs.size);
+    if bs.group {
+        format!("{}{}", group_digits(n, &THOUSANDS_SEP), bs.suffix)
     } else {
-        Err(anyhow!("get groupname error"))
+        format!("{}{}", n, bs.suffix)
+    }
+}
+
+/// Extent count followed by whatever part of the file is shared, inline or
+/// unwritten, e.g. `12 ext, 1.5M shared`.
+fn format_extents(extents: &Extents, conf: &LssConf) -> String {
+    let mut res = format!("{} ext", extents.count);
+    for (bytes, what) in [
+        (extents.shared, "shared"),
+        (extents.inline, "inline"),
+        (extents.unwritten, "unwritten"),
+    ] {
+        if bytes > 0 {
+            res.push_str(&format!(", {} {what}", format_size(bytes, conf, 1)));
+        }
     }
+    res
 }
 
-fn get_owner_and_group(md: &Metadata) -> Result<(String, String)> {
-    Ok((get_username(md.uid())?, get_groupname(md.gid())?))
+/// Formats a count of 512-byte blocks, in 1K units unless `--block-size` says otherwise.
+fn format_blocks(nblocks: u64, conf: &LssConf) -> String {
+    format_size(nblocks * 512, conf, 1024)
 }
 
-fn get_mode(md: &Metadata) -> String {
-    let perm = md.permissions();
-    let mode = perm.mode();
+/// Runs a reentrant `getpw*_r`/`getgr*_r` lookup, growing the scratch buffer
+/// while it reports `ERANGE`.
+fn lookup_with_buffer(mut call: impl FnMut(&mut [u8]) -> i32) -> io::Result<()> {
+    let mut buf = vec![0u8; 1024];
+    loop {
+        match call(&mut buf) {
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            // not-found is reported through errno by some libcs
+            0 | libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM => return Ok(()),
+            err => return Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+fn lookup_username(uid: u32) -> Result<String> {
+    let mut name = None;
+    lookup_with_buffer(|buf| {
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut res: *mut libc::passwd = std::ptr::null_mut();
+        let ret = unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut passwd,
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+                &mut res,
+            )
+        };
+        if ret == 0 && !res.is_null() {
+            name = Some(
+                unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) }
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+        ret
+    })
+    .map_err(|e| anyhow!("get username error: {e}"))?;
+
+    Ok(name.unwrap_or_else(|| uid.to_string()))
+}
+
+fn lookup_groupname(gid: u32) -> Result<String> {
+    let mut name = None;
+    lookup_with_buffer(|buf| {
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut res: *mut libc::group = std::ptr::null_mut();
+        let ret = unsafe {
+            libc::getgrgid_r(
+                gid,
+                &mut group,
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+                &mut res,
+            )
+        };
+        if ret == 0 && !res.is_null() {
+            name = Some(
+                unsafe { std::ffi::CStr::from_ptr(group.gr_name) }
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+        ret
+    })
+    .map_err(|e| anyhow!("get groupname error: {e}"))?;
+
+    Ok(name.unwrap_or_else(|| gid.to_string()))
+}
+
+/// uid/gid to name caches shared by every lookup of the invocation, since a
+/// listing rarely has more than a handful of distinct owners.
+static USERNAMES: LazyLock<Mutex<HashMap<u32, String>>> = LazyLock::new(Default::default);
+static GROUPNAMES: LazyLock<Mutex<HashMap<u32, String>>> = LazyLock::new(Default::default);
+
+fn get_cached(
+    cache: &Mutex<HashMap<u32, String>>,
+    id: u32,
+    lookup: fn(u32) -> Result<String>,
+) -> Result<String> {
+    if let Some(name) = cache.lock().ok().and_then(|c| c.get(&id).cloned()) {
+        return Ok(name);
+    }
+    let name = lookup(id)?;
+    if let Ok(mut c) = cache.lock() {
+        c.insert(id, name.clone());
+    }
+    Ok(name)
+}
+
+fn get_username(uid: u32) -> Result<String> {
+    get_cached(&USERNAMES, uid, lookup_username)
+}
+
+fn get_groupname(gid: u32) -> Result<String> {
+    get_cached(&GROUPNAMES, gid, lookup_groupname)
+}
+
+fn get_owner_and_group(st: &Stat) -> Result<(String, String)> {
+    Ok((get_username(st.uid)?, get_groupname(st.gid)?))
+}
+
+/// `st_dev` as `major:minor`.
+fn get_device(dev: u64) -> String {
+    format!("{}:{}", libc::major(dev), libc::minor(dev))
+}
+
+fn get_locks_str(locks: &[Lock]) -> String {
+    if locks.is_empty() {
+        return "-".to_string();
+    }
+    locks
+        .iter()
+        .map(Lock::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn get_styled_git_status(status: &str, color: DisplayColor) -> String {
+    if let DisplayColor::Empty = color {
+        return status.to_string();
+    }
+    status
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            '-' => c.to_string().dimmed().to_string(),
+            '?' | 'U' => c.to_string().red().to_string(),
+            '!' => c.to_string().bright_black().to_string(),
+            _ if i == 0 => c.to_string().green().to_string(),
+            _ => c.to_string().red().to_string(),
+        })
+        .collect()
+}
+
+fn get_mode(st: &Stat) -> String {
+    let mode = st.mode;
 
     let mut builder = String::with_capacity(10);
 
-    let ft = md.file_type();
-    builder.push(if ft.is_dir() {
+    builder.push(if st.is_dir() {
         'd'
-    } else if ft.is_file() {
+    } else if st.is_file() {
         '-'
-    } else if ft.is_symlink() {
+    } else if st.is_symlink() {
         'l'
+    } else if st.is_fifo() {
+        'p'
+    } else if st.is_socket() {
+        's'
+    } else if st.is_block_device() {
+        'b'
+    } else if st.is_char_device() {
+        'c'
     } else {
         '?'
     });
@@ -496,98 +1131,513 @@ fn get_mode(md: &Metadata) -> String {
 
 #[derive(Debug, Default)]
 struct Maxs {
+    mode: usize,
     size: usize,
     hsize: usize,
     blocks: usize,
     name: usize,
     owner: usize,
     group: usize,
+    nlink: usize,
+    inode: usize,
+    dev: usize,
+    fstype: usize,
+    source: usize,
+    context: usize,
+    caps: usize,
+    alloc: usize,
+    segments: usize,
+    extents: usize,
+    open_by: usize,
+    locks: usize,
+    link_group: usize,
 }
 
-fn read_dir<P: AsRef<Path>>(path: P, all: bool) -> Result<(Vec<FEntry>, Maxs)> {
-    let mut res = Vec::new();
+impl Maxs {
+    fn update(&mut self, fe: &FEntry, conf: &LssConf) {
+        self.mode = self.mode.max(fe.mode.len());
+        self.blocks = self.blocks.max(fe.hblocks.len());
+        self.nlink = self.nlink.max(fe.get_nlink().len());
+        self.inode = self.inode.max(fe.get_ino().len());
+        self.dev = self.dev.max(get_device(fe.dev).len());
+        if let Some((fstype, source)) = &fe.fs {
+            self.fstype = self.fstype.max(fstype.len());
+            self.source = self.source.max(source.len());
+        }
+        self.context = self.context.max(fe.context.as_ref().map_or(1, |c| c.len()));
+        self.caps = self.caps.max(fe.caps.as_ref().map_or(1, |c| c.len()));
+        self.name = self.name.max(fe.name.len());
+        self.size = self.size.max(fe.size.to_string().len());
+        self.hsize = self.hsize.max(fe.hsize.len());
+        self.alloc = self.alloc.max(fe.halloc.len());
+        self.segments = self.segments.max(fe.hsegments.len());
+        self.extents = self.extents.max(fe.hextents.len());
+        self.locks = self.locks.max(get_locks_str(&fe.locks).len());
+        self.open_by = self.open_by.max(fe.open_by.as_ref().map_or(0, |o| o.len()));
+        self.owner = self.owner.max(fe.get_owner(conf).len());
+        self.group = self.group.max(fe.get_group(conf).len());
+    }
+}
 
-    let mut maxs = Maxs::default();
+#[derive(Debug, Default)]
+struct DirUsage {
+    apparent: u64,
+    allocated: u64,
+    partial: bool,
+    /// Inodes with more than one link counted in this tree, with their
+    /// (apparent, allocated) sizes, so that `read_dir` can drop the ones an
+    /// earlier entry already accounted for.
+    links: HashMap<(u64, u64), (u64, u64)>,
+}
 
-    let mut dlen = 0;
-    let mut total = 0;
-    let mut max_name = String::new();
-    for f in fs::read_dir(&path)? {
-        dlen += 1;
-        let f = f?;
-        let md = f.metadata()?;
+/// Recursive apparent and allocated size of `path`, like `du`. Symlinks are not
+/// followed and hardlinked inodes are counted once per tree. Unreadable
+/// subtrees mark the result partial. With `one_fs`, entries on other devices
+/// are skipped like `du -x` does.
+fn get_dir_usage(path: &Path, st: &Stat, one_fs: Option<u64>) -> DirUsage {
+    let mut usage = DirUsage {
+        apparent: st.size,
+        allocated: st.blocks * 512,
+        ..Default::default()
+    };
+    add_dir_usage(path, &mut usage, one_fs);
+    usage
+}
 
-        let blocks = md.blocks() / 2;
-        if blocks.to_string().len() > maxs.blocks {
-            maxs.blocks = blocks.to_string().len();
+fn add_dir_usage(path: &Path, usage: &mut DirUsage, one_fs: Option<u64>) {
+    let dir = match fs::read_dir(path) {
+        Ok(dir) => dir,
+        Err(e) => {
+            warn!("cannot read `{}`: {}", path.display(), e);
+            usage.partial = true;
+            return;
         }
-
-        let name = f
-            .file_name()
-            .to_str()
-            .ok_or(anyhow!("non-valid unicode in name"))?
-            .to_string();
-        if !all && name.starts_with(".") {
-            dlen -= 1;
+    };
+    for f in dir {
+        let Ok((f, md)) = f.and_then(|f| f.metadata().map(|md| (f, md))) else {
+            usage.partial = true;
+            continue;
+        };
+        if one_fs.is_some_and(|dev| md.dev() != dev) {
             continue;
         }
+        let (apparent, allocated) = (md.size(), md.blocks() * 512);
+        if md.nlink() > 1 && !md.is_dir() {
+            let key = (md.dev(), md.ino());
+            if usage.links.contains_key(&key) {
+                continue;
+            }
+            usage.links.insert(key, (apparent, allocated));
+        }
+        usage.apparent += apparent;
+        usage.allocated += allocated;
+        if md.is_dir() {
+            add_dir_usage(&f.path(), usage, one_fs);
+        }
+    }
+}
 
-        if name.len() > maxs.name {
-            maxs.name = name.len();
-            max_name = name.clone();
+const SELINUX_XATTR: &str = "security.selinux";
+
+/// The `security.selinux` label of `path`. Unlabeled entries and filesystems
+/// without SELinux support have none.
+fn get_context(path: &Path) -> Option<String> {
+    match xattr::get(path, SELINUX_XATTR) {
+        Ok(label) => {
+            let label = label.strip_suffix(b"\0").unwrap_or(&label);
+            Some(String::from_utf8_lossy(label).into_owned())
+        }
+        Err(e) if matches!(e.raw_os_error(), Some(libc::ENODATA | libc::ENOTSUP)) => None,
+        Err(e) => {
+            warn!("cannot read the context of `{}`: {}", path.display(), e);
+            None
         }
+    }
+}
 
-        let ftype = if md.is_dir() {
-            FType::Dir
-        } else if md.is_symlink() {
-            match fs::read_link(f.path()) {
-                Ok(p) => FType::Symlink(
-                    p.to_str()
-                        .ok_or(anyhow!("non-valid unicode in name"))?
-                        .to_string(),
-                ),
-                Err(_) => FType::BrokenSymlink,
-            }
-        } else if md.is_file() {
-            FType::File(md.is_file() && md.permissions().mode() & 0o111 != 0)
-        } else {
-            FType::Other
-        };
+/// The character GNU ls appends to the mode: `+` for an ACL or any other
+/// extended attribute, `.` when the only one is an SELinux context.
+fn get_mode_marker(xattrs: &[Xattr], acl: Option<&Acl>) -> Option<char> {
+    if acl.is_some_and(Acl::is_extended) || xattrs.iter().any(|a| a.name != SELINUX_XATTR) {
+        Some('+')
+    } else if !xattrs.is_empty() {
+        Some('.')
+    } else {
+        None
+    }
+}
 
-        let modified = Time::from_modified(&md)?;
+/// `STATX_*` fields the current invocation actually displays, sorts or
+/// filters on; everything else is left for the filesystem to skip.
+fn get_statx_mask(conf: &LssConf) -> u32 {
+    let mut mask = libc::STATX_TYPE | libc::STATX_MODE;
+    if conf.inode {
+        mask |= libc::STATX_INO;
+    }
+    if conf.hardlinks {
+        mask |= libc::STATX_INO | libc::STATX_SIZE;
+    }
+    if conf.extents || conf.open_by || conf.locks || conf.locked {
+        mask |= libc::STATX_INO;
+    }
+    if conf.is_long() {
+        mask |= libc::STATX_NLINK
+            | libc::STATX_UID
+            | libc::STATX_GID
+            | libc::STATX_SIZE
+            | libc::STATX_BLOCKS
+            | libc::STATX_MTIME;
+    }
+    if conf.alloc || conf.alloc_sort.is_some() {
+        mask |= libc::STATX_SIZE | libc::STATX_BLOCKS;
+    }
+    if conf.size_sort || conf.total_size || conf.min_size.is_some() || conf.max_size.is_some() {
+        mask |= libc::STATX_SIZE | libc::STATX_BLOCKS;
+    }
+    if conf.newer.is_some() || conf.older.is_some() {
+        mask |= libc::STATX_MTIME;
+    }
+    if conf.btime {
+        mask |= libc::STATX_BTIME;
+    }
+    if conf.query.is_some() {
+        mask |= libc::STATX_BASIC_STATS | libc::STATX_BTIME;
+    }
+    mask
+}
 
-        let size = md.size();
-        if size.to_string().len() > maxs.size {
-            maxs.size = size.to_string().len();
-        }
-        total += size;
-        let hsize = get_human_readable_size(size);
-        if hsize.len() > maxs.hsize {
-            maxs.hsize = hsize.len();
+/// Collects everything about one entry that needs a syscall. Runs on the
+/// worker pool, so sizes are left unformatted until `read_dir` has settled
+/// hardlinks shared between directories.
+fn read_entry(
+    f: &fs::DirEntry,
+    conf: &LssConf,
+    root_dev: u64,
+) -> Result<(FEntry, Option<DirUsage>)> {
+    let st = Stat::lstat(&f.path(), get_statx_mask(conf))?;
+    let name = f
+        .file_name()
+        .to_str()
+        .ok_or(anyhow!("non-valid unicode in name"))?
+        .to_string();
+
+    let ftype = if st.is_dir() {
+        FType::Dir
+    } else if st.is_symlink() {
+        match fs::read_link(f.path()) {
+            Ok(p) => FType::Symlink(
+                p.to_str()
+                    .ok_or(anyhow!("non-valid unicode in name"))?
+                    .to_string(),
+            ),
+            Err(_) => FType::BrokenSymlink,
         }
+    } else if st.is_file() {
+        FType::File(st.mode & 0o111 != 0)
+    } else if st.is_fifo() {
+        FType::Fifo
+    } else if st.is_socket() {
+        FType::Socket
+    } else if st.is_block_device() {
+        FType::BlockDevice
+    } else if st.is_char_device() {
+        FType::CharDevice
+    } else {
+        FType::Other
+    };
 
-        let (owner, group) = get_owner_and_group(&md)?;
-        if owner.len() > maxs.owner {
-            maxs.owner = owner.len();
+    let crosses_fs = conf.one_file_system && st.dev != root_dev;
+    let usage = if conf.total_size && st.is_dir() && !crosses_fs {
+        let one_fs = conf.one_file_system.then_some(root_dev);
+        Some(get_dir_usage(&f.path(), &st, one_fs))
+    } else {
+        None
+    };
+    // names are resolved even with `-n`, which `--where owner = ...` matches
+    let (owner, group) = if !st.has(libc::STATX_UID) || !st.has(libc::STATX_GID) {
+        ("-".to_string(), "-".to_string())
+    } else {
+        get_owner_and_group(&st)?
+    };
+    let xattrs = if conf.is_long() {
+        xattr::read_all(&f.path(), conf.xattr).unwrap_or_else(|e| {
+            warn!("cannot read xattrs of `{}`: {}", f.path().display(), e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    let read_acl = |name| {
+        if !xattrs.iter().any(|a| a.name == name) {
+            return None;
         }
-        if group.len() > maxs.group {
-            maxs.group = group.len();
+        Acl::read(&f.path(), name).unwrap_or_else(|e| {
+            warn!("cannot read `{}` of `{}`: {}", name, f.path().display(), e);
+            None
+        })
+    };
+    let acl = read_acl(acl::ACCESS);
+    let default_acl = if conf.acl {
+        read_acl(acl::DEFAULT)
+    } else {
+        None
+    };
+    let context = if conf.context {
+        get_context(&f.path())
+    } else {
+        None
+    };
+    // Without a long listing there is no xattr list to check first, but the
+    // colour still needs the capabilities of every regular file
+    let caps = if st.is_file()
+        && (conf.caps || conf.colorized())
+        && (!conf.is_long() || xattrs.iter().any(|a| a.name == caps::XATTR))
+    {
+        Caps::read(&f.path()).unwrap_or_else(|e| {
+            warn!(
+                "cannot read the capabilities of `{}`: {}",
+                f.path().display(),
+                e
+            );
+            None
+        })
+    } else {
+        None
+    };
+    let uses_flags = conf.attrs || conf.query.as_ref().is_some_and(Query::uses_flags);
+    let flags = if uses_flags && (st.is_file() || st.is_dir()) {
+        flags::get_inode_flags(&f.path()).unwrap_or_else(|e| {
+            warn!("cannot read the flags of `{}`: {}", f.path().display(), e);
+            None
+        })
+    } else {
+        None
+    };
+    let segments = if conf.alloc_extents && st.is_file() {
+        alloc::get_segments(&f.path(), st.size)
+            .inspect_err(|e| warn!("cannot walk the holes of `{}`: {}", f.path().display(), e))
+            .ok()
+    } else {
+        None
+    };
+    let extents = if conf.extents && st.is_file() {
+        extents::get_extents(&f.path()).unwrap_or_else(|e| {
+            warn!("cannot map the extents of `{}`: {}", f.path().display(), e);
+            None
+        })
+    } else {
+        None
+    };
+    let access = if conf.access {
+        access::get_effective_access(&f.path())
+    } else {
+        String::new()
+    };
+    let user_access = match &conf.as_user {
+        Some(creds) if st.is_symlink() => access::get_access_through_link(creds, &f.path()),
+        Some(creds) => access::get_access_for(
+            creds,
+            &access::Target {
+                mode: st.mode,
+                uid: st.uid,
+                gid: st.gid,
+                is_dir: st.is_dir(),
+                acl: acl.as_ref(),
+            },
+        ),
+        None => String::new(),
+    };
+    let mut mode = get_mode(&st);
+    mode.extend(get_mode_marker(&xattrs, acl.as_ref()));
+
+    let fe = FEntry {
+        name,
+        path: f.path(),
+        nblocks: st.blocks,
+        hblocks: String::new(),
+        blksize: st.blksize,
+        segments,
+        halloc: String::new(),
+        hsegments: String::new(),
+        extents,
+        hextents: String::new(),
+        ftype,
+        modified: st.mtime.clone(),
+        created: st.btime.clone(),
+        size: st.size,
+        hsize: String::new(),
+        nlink: st.nlink,
+        mask: st.mask,
+        ino: st.ino,
+        dev: st.dev,
+        mount_point: false,
+        fs: None,
+        uid: st.uid,
+        gid: st.gid,
+        owner,
+        group,
+        mode,
+        octal: format!("{:04o}", st.mode & 0o7777),
+        access,
+        user_access,
+        attributes: st.attributes_str(),
+        flags,
+        xattrs,
+        acl,
+        default_acl,
+        context,
+        caps: caps.map(|c| c.format()).filter(|c| !c.is_empty()),
+        open_by: None,
+        locks: Vec::new(),
+        link_group: None,
+        git: None,
+    };
+    Ok((fe, usage))
+}
+
+fn read_dir<P: AsRef<Path>>(path: P, conf: &LssConf) -> Result<(Vec<FEntry>, Maxs)> {
+    let mut res = Vec::new();
+
+    let git = if conf.git || conf.query.as_ref().is_some_and(Query::uses_git) {
+        let git = GitStatus::for_dir(&path);
+        if git.is_none() {
+            warn!(
+                "`{}` is not inside a git work tree",
+                path.as_ref().display()
+            );
         }
+        git
+    } else {
+        None
+    };
 
-        let mode = get_mode(&md);
+    let mut maxs = Maxs::default();
 
-        res.push(FEntry {
-            name,
-            path: f.path(),
-            nblocks: blocks,
-            ftype,
-            modified,
-            size,
-            hsize,
-            owner,
-            group,
-            mode,
+    let filter = Filter::from_conf(conf);
+    let mut seen = HashSet::new();
+
+    let mut entries = Vec::new();
+    for f in fs::read_dir(&path)? {
+        let f = f?;
+        if conf.all || !f.file_name().as_bytes().starts_with(b".") {
+            entries.push(f);
+        }
+    }
+    let dlen = entries.len();
+    let locks = if conf.locks || conf.locked {
+        locks::read_locks().unwrap_or_else(|e| {
+            warn!("cannot read /proc/locks: {}", e);
+            HashMap::new()
         })
+    } else {
+        HashMap::new()
+    };
+    let open_files = conf.open_by.then(|| {
+        let start = Instant::now();
+        let open_files = OpenFiles::scan();
+        info!("scanned open files in {:?}", start.elapsed());
+        open_files
+    });
+    let root_dev = fs::metadata(&path)?.dev();
+    let root = fs::canonicalize(&path)?;
+
+    let threads = conf.threads.unwrap_or_else(pool::default_threads).max(1);
+    let start = Instant::now();
+    let collected = pool::parallel_map(&entries, threads, |f| read_entry(f, conf, root_dev));
+    info!(
+        "collected {} entries in {:?} on {} thread(s)",
+        dlen,
+        start.elapsed(),
+        threads.min(dlen).max(1)
+    );
+
+    let mut total = 0;
+    let mut max_name = String::new();
+    for entry in collected {
+        let (mut fe, usage) = entry?;
+
+        let mut partial = false;
+        let recursive = usage.is_some();
+        if let Some(usage) = usage {
+            let (mut apparent, mut allocated) = (usage.apparent, usage.allocated);
+            for (key, (a, b)) in usage.links {
+                if !seen.insert(key) {
+                    apparent -= a;
+                    allocated -= b;
+                }
+            }
+            fe.size = apparent;
+            fe.nblocks = allocated / 512;
+            partial = usage.partial;
+        }
+        fe.hsize = match recursive || fe.has(libc::STATX_SIZE) {
+            true if conf.total_size => format!(
+                "{}/{}",
+                format_size(fe.size, conf, 1),
+                format_size(fe.nblocks * 512, conf, 1)
+            ),
+            true => format_size(fe.size, conf, 1),
+            false => "-".to_string(),
+        };
+        if partial {
+            fe.hsize.push('+');
+        }
+        fe.hblocks = match recursive || fe.has(libc::STATX_BLOCKS) {
+            true => format_blocks(fe.nblocks, conf),
+            false => "-".to_string(),
+        };
+        if conf.extents {
+            fe.hextents = match &fe.extents {
+                Some(extents) => format_extents(extents, conf),
+                None if matches!(fe.ftype, FType::File(_)) => "?".to_string(),
+                None => "-".to_string(),
+            };
+        }
+        if conf.alloc {
+            // only regular files have a meaningful ratio: fast symlinks keep
+            // their target in the inode and directories are sized recursively
+            let is_file = matches!(fe.ftype, FType::File(_));
+            fe.halloc = match is_file {
+                true => alloc::format(fe.size, fe.nblocks * 512, fe.blksize),
+                false => "- ".to_string(),
+            };
+            fe.hsegments = match fe.segments {
+                Some(segments) => alloc::format_segments(segments),
+                None if is_file => "?".to_string(),
+                None => "-".to_string(),
+            };
+        }
+        fe.locks = locks.get(&(fe.dev, fe.ino)).cloned().unwrap_or_default();
+        let is_dir = matches!(fe.ftype, FType::Dir);
+        fe.git = git.as_ref().map(|g| g.status(&fe.name, is_dir));
+
+        if !filter.matches(&fe) {
+            continue;
+        }
+        fe.mount_point = fe.dev != root_dev || MOUNTS.is_mount_point(&root.join(&fe.name));
+        if let Some(open_files) = &open_files {
+            let holders = open_files.holders(fe.dev, fe.ino, &root.join(&fe.name));
+            fe.open_by = Some(if holders.is_empty() {
+                "-".to_string()
+            } else {
+                holders
+                    .iter()
+                    .map(|h| format!("{}[{}]", h.comm, h.pid))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            });
+        }
+        if conf.fs {
+            fe.fs = MOUNTS
+                .find(fe.dev)
+                .map(|m| (m.fstype.clone(), m.source.clone()));
+        }
+        if fe.name.len() > maxs.name {
+            max_name = fe.name.clone();
+        }
+        total += fe.size;
+        maxs.update(&fe, conf);
+        res.push(fe)
     }
     match res.len() {
         0 => error!(
@@ -605,7 +1655,7 @@ fn read_dir<P: AsRef<Path>>(path: P, all: bool) -> Result<(Vec<FEntry>, Maxs)> {
         ),
         l if l == dlen => info!(
             "found all ({}/{}) entries in `{}`",
-            res.len().to_string(),
+            res.len(),
             dlen,
             path.as_ref().display(),
         ),
@@ -623,10 +1673,78 @@ fn read_dir<P: AsRef<Path>>(path: P, all: bool) -> Result<(Vec<FEntry>, Maxs)> {
 
     Ok((res, maxs))
 }
-fn sort(dir: &mut Vec<FEntry>, nrev: bool, bsize: bool) {
-    if bsize {
+
+#[derive(Debug, Default)]
+struct HardlinkSummary {
+    groups: usize,
+    members: usize,
+    stored: u64,
+    apparent: u64,
+}
+
+/// Numbers the sets of listed entries that share a `(dev, ino)`, in display
+/// order, and totals what the listing occupies once shared inodes are only
+/// counted once.
+fn group_hardlinks(dir: &mut [FEntry], maxs: &mut Maxs) -> HardlinkSummary {
+    let mut members: HashMap<(u64, u64), usize> = HashMap::new();
+    for fe in dir.iter() {
+        *members.entry((fe.dev, fe.ino)).or_default() += 1;
+    }
+
+    let mut summary = HardlinkSummary::default();
+    let mut ids = HashMap::new();
+    for fe in dir.iter_mut() {
+        let key = (fe.dev, fe.ino);
+        summary.apparent += fe.size;
+        if members[&key] < 2 {
+            summary.stored += fe.size;
+            continue;
+        }
+        summary.members += 1;
+        let next = ids.len() + 1;
+        let id = *ids.entry(key).or_insert_with(|| {
+            summary.stored += fe.size;
+            next
+        });
+        fe.link_group = Some(id);
+    }
+    summary.groups = ids.len();
+    // no column at all when nothing is hardlinked
+    maxs.link_group = match ids.len() {
+        0 => 0,
+        n => n.to_string().len() + 1,
+    };
+
+    info!(
+        "hardlinks: {} groups, {} members",
+        summary.groups, summary.members
+    );
+    summary
+}
+
+fn get_styled_link_group(id: usize, width: usize, color: DisplayColor) -> String {
+    let tag = format!("{:<width$}", format!("#{id}"));
+    match color {
+        DisplayColor::Empty => tag,
+        DisplayColor::Standart => match id % 6 {
+            1 => tag.red(),
+            2 => tag.green(),
+            3 => tag.yellow(),
+            4 => tag.blue(),
+            5 => tag.magenta(),
+            _ => tag.cyan(),
+        }
+        .to_string(),
+    }
+}
+
+fn sort(dir: &mut [FEntry], nrev: bool, bsize: bool, alloc: Option<AllocSort>) {
+    if let Some(alloc) = alloc {
+        info!("sortnig by {} ({:?})", "allocation".bold(), alloc);
+        dir.sort_by_key(|fe| (alloc.key(fe.size, fe.nblocks * 512), fe.name.clone()))
+    } else if bsize {
         info!("sortnig by {}", "size".bold());
-        dir.sort_by_key(|fe| fe.size)
+        dir.sort_by_key(|fe| (fe.size, fe.nblocks))
     } else {
         info!("sortnig by {}", "name".bold());
         dir.sort_by_key(|fe| fe.name.clone())
@@ -644,6 +1762,27 @@ fn format_long_info(names: Vec<String>) -> String {
 
     names.join("\n")
 }
+/// Terminal columns taken by `s`: ANSI escape sequences take none, and wide
+/// characters two. Nerd Font glyphs sit in the Private Use Area and count one.
+fn display_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI: `ESC [`, parameters, then a final byte in `@..=~`
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        width += c.width().unwrap_or(0);
+    }
+    width
+}
 fn calculate_optimal_layout(names: &[String], term_cols: usize) -> usize {
     let total_items = names.len();
 
@@ -670,12 +1809,11 @@ fn calculate_column_widths(names: &[String], rows: usize) -> Vec<usize> {
     let cols = total_items.div_ceil(rows);
     let mut col_widths = vec![0; cols];
 
-    for col in 0..cols {
+    for (col, width) in col_widths.iter_mut().enumerate() {
         for row in 0..rows {
             let idx = col * rows + row;
             if idx < total_items {
-                col_widths[col] = col_widths[col]
-                    .max(names[idx].len().checked_sub(9).unwrap_or(names[idx].len()));
+                *width = (*width).max(display_width(&names[idx]));
             }
         }
     }
@@ -699,7 +1837,7 @@ fn format_with_terminal_width(names: Vec<String>, width: Option<usize>, line: bo
     };
     info!("col {term_cols}");
 
-    let total_width = names.iter().map(|n| n.len() - 9).sum::<usize>() + names.len() - 1;
+    let total_width = names.iter().map(|n| display_width(n)).sum::<usize>() + names.len() - 1;
     info!("all file width {total_width}");
     if total_width <= term_cols {
         info!("passed in one line");
@@ -718,11 +1856,11 @@ fn format_with_terminal_width(names: Vec<String>, width: Option<usize>, line: bo
     for row in 0..rows {
         let mut line = String::new();
 
-        for col in 0..max_cols {
+        for (col, width) in col_widths.iter().enumerate() {
             let idx = col * rows + row;
             if idx < names.len() {
                 let name = &names[idx];
-                let padding = col_widths[col] - (name.len() - 9);
+                let padding = width - display_width(name);
                 line.push_str(name);
                 if col < max_cols - 1 {
                     line.push_str(&" ".repeat(padding + 2));
@@ -741,46 +1879,132 @@ fn format_with_terminal_width(names: Vec<String>, width: Option<usize>, line: bo
 fn main() -> Result<()> {
     env_logger::init();
     info!("START LOGGING");
+    // only LC_NUMERIC, for the `'` block size grouping; the rest stays C
+    unsafe { libc::setlocale(libc::LC_NUMERIC, c"".as_ptr()) };
 
     info!("parsing cmd arguments");
     let conf = LssConf::parse();
-    let (mut dir, maxs) = read_dir(&conf.path, conf.all)?;
-    sort(&mut dir, conf.reverse, conf.size_sort);
+    if let Some(path) = &conf.explain_mode {
+        print!("{}", explain::explain_mode(path)?);
+        return Ok(());
+    }
+    let (mut dir, mut maxs) = read_dir(&conf.path, &conf)?;
+    sort(&mut dir, conf.reverse, conf.size_sort, conf.alloc_sort);
+    let hardlinks = conf.hardlinks.then(|| group_hardlinks(&mut dir, &mut maxs));
 
-    if conf.long {
+    if conf.is_long() {
         let tblocks: u64 = dir.iter().map(|fe| fe.nblocks).sum();
-        let names = dir
-            .iter()
-            .map(|f| {
-                f.to_fixed_str(
-                    conf.humanize,
-                    &maxs,
-                    conf.blocks,
-                    conf.color,
-                    conf.quoted,
-                    conf.link,
-                )
-            })
-            .collect();
-        println!("total {}", tblocks);
+        let names = dir.iter().map(|f| f.to_fixed_str(&conf, &maxs)).collect();
+        println!("total {}", format_blocks(tblocks, &conf));
         println!("{}", format_long_info(names));
     } else if conf.absolute {
-        let names = dir
-            .iter()
-            .map(|f| f.to_abs_str(conf.quoted))
-            .flatten()
-            .collect();
+        let names = dir.iter().flat_map(|f| f.to_abs_str(conf.quoted)).collect();
         println!("{}", format_long_info(names));
     } else {
-        let names = dir
-            .iter()
-            .map(|f| f.to_str(conf.color, conf.quoted))
-            .collect();
+        let names = dir.iter().map(|f| f.to_str(&conf, &maxs)).collect();
         print!(
             "{} ",
             format_with_terminal_width(names, conf.width, conf.line)
         );
         println!();
     }
+    if conf.extents {
+        // hardlinks map the same extents without any reflink involved
+        let mut inodes = HashSet::new();
+        let mapped: Vec<&Extents> = dir
+            .iter()
+            .filter(|fe| inodes.insert((fe.dev, fe.ino)))
+            .filter_map(|fe| fe.extents.as_ref())
+            .collect();
+        let (shared, saved) = extents::get_reflink_savings(mapped.iter().copied());
+        println!(
+            "extents: {} in {} file(s), {} shared, {} saved by reflinks within this listing",
+            mapped.iter().map(|e| e.count).sum::<u64>(),
+            mapped.len(),
+            format_size(shared, &conf, 1),
+            format_size(saved, &conf, 1),
+        );
+    }
+    if let Some(summary) = hardlinks {
+        println!(
+            "hardlinks: {} group(s) of {} entries, {} stored of {} apparent ({} saved)",
+            summary.groups,
+            summary.members,
+            format_size(summary.stored, &conf, 1),
+            format_size(summary.apparent, &conf, 1),
+            format_size(summary.apparent - summary.stored, &conf, 1),
+        );
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(args: &[&str]) -> Vec<String> {
+        let conf =
+            LssConf::try_parse_from(std::iter::once("lss").chain(args.iter().copied())).unwrap();
+        let (dir, _) = read_dir(&conf.path, &conf).unwrap();
+        let mut names: Vec<String> = dir.into_iter().map(|fe| fe.name).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn unit_multipliers() {
+        assert_eq!(get_unit_multiplier(""), Some(1));
+        assert_eq!(get_unit_multiplier("B"), Some(1));
+        assert_eq!(get_unit_multiplier("K"), Some(1024));
+        assert_eq!(get_unit_multiplier("k"), Some(1024));
+        assert_eq!(get_unit_multiplier("KiB"), Some(1024));
+        assert_eq!(get_unit_multiplier("KB"), Some(1000));
+        assert_eq!(get_unit_multiplier("M"), Some(1 << 20));
+        assert_eq!(get_unit_multiplier("GB"), Some(1_000_000_000));
+        assert_eq!(get_unit_multiplier("E"), Some(1 << 60));
+        for unit in ["Z", "Kb", "KiBB", "iB", "x"] {
+            assert_eq!(get_unit_multiplier(unit), None, "{unit:?}");
+        }
+    }
+
+    #[test]
+    fn block_sizes() {
+        let bs = |s| parse_block_size(s).map(|b| (b.size, b.suffix, b.group));
+        assert_eq!(bs("1024"), Ok((1024, String::new(), false)));
+        assert_eq!(bs("1K"), Ok((1024, String::new(), false)));
+        assert_eq!(bs("K"), Ok((1024, "K".to_string(), false)));
+        assert_eq!(bs("MB"), Ok((1_000_000, "MB".to_string(), false)));
+        assert_eq!(bs("4KiB"), Ok((4096, String::new(), false)));
+        assert_eq!(bs("'1"), Ok((1, String::new(), true)));
+        assert_eq!(bs("'K"), Ok((1024, "K".to_string(), true)));
+        for s in ["", "'", "0", "0K", "1Q", "-1", "99999999999E"] {
+            assert!(parse_block_size(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn digit_grouping() {
+        assert_eq!(group_digits(0, ","), "0");
+        assert_eq!(group_digits(999, ","), "999");
+        assert_eq!(group_digits(1000, ","), "1,000");
+        assert_eq!(group_digits(10485760, ","), "10,485,760");
+        assert_eq!(group_digits(1234567, "\u{202f}"), "1\u{202f}234\u{202f}567");
+        assert_eq!(group_digits(10485760, ""), "10485760");
+    }
+
+    #[test]
+    fn where_git_without_git_flag() {
+        let dir = git::tests::fixture("where");
+        let path = dir.to_str().unwrap();
+        assert_eq!(list(&["--where", "git = '-M'", path]), ["modified"]);
+        assert_eq!(
+            list(&["--git", "--where", "git ~ '*M*'", path]),
+            ["modified", "staged"]
+        );
+        assert_eq!(
+            list(&["--where", "git in ('??', R-)", path]),
+            ["new", "untracked"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
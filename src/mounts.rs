@@ -0,0 +1,150 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs, io,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use log::warn;
+
+/// Mount table of this process, read once on first use.
+pub static MOUNTS: LazyLock<MountTable> = LazyLock::new(|| {
+    MountTable::load().unwrap_or_else(|e| {
+        warn!("cannot read /proc/self/mountinfo: {}", e);
+        MountTable::default()
+    })
+});
+
+#[derive(Debug)]
+pub struct Mount {
+    pub dev: u64,
+    pub mount_point: PathBuf,
+    pub fstype: String,
+    pub source: String,
+}
+
+#[derive(Debug, Default)]
+pub struct MountTable {
+    mounts: Vec<Mount>,
+    mount_points: HashSet<PathBuf>,
+}
+
+/// Undoes the octal escaping mountinfo applies to spaces, tabs, newlines
+/// and backslashes.
+fn unescape(s: &str) -> OsString {
+    let b = s.as_bytes();
+    let mut res = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let code = b.get(i + 1..i + 4).and_then(|o| {
+            let o = std::str::from_utf8(o).ok()?;
+            u8::from_str_radix(o, 8).ok()
+        });
+        match code {
+            Some(c) if b[i] == b'\\' => {
+                res.push(c);
+                i += 4;
+            }
+            _ => {
+                res.push(b[i]);
+                i += 1;
+            }
+        }
+    }
+    OsString::from_vec(res)
+}
+
+/// One line of `/proc/self/mountinfo`:
+/// `id parent major:minor root mount-point options [optional...] - fstype source super-options`
+fn parse_line(line: &str) -> Option<Mount> {
+    let (left, right) = line.split_once(" - ")?;
+    let left: Vec<&str> = left.split(' ').collect();
+    let mut right = right.split(' ');
+
+    let (major, minor) = left.get(2)?.split_once(':')?;
+    Some(Mount {
+        dev: libc::makedev(major.parse().ok()?, minor.parse().ok()?),
+        mount_point: PathBuf::from(unescape(left.get(4)?)),
+        fstype: right.next()?.to_string(),
+        source: unescape(right.next()?).to_string_lossy().into_owned(),
+    })
+}
+
+impl MountTable {
+    pub fn load() -> io::Result<Self> {
+        let info = fs::read_to_string("/proc/self/mountinfo")?;
+        let mounts: Vec<Mount> = info.lines().filter_map(parse_line).collect();
+        let mount_points = mounts.iter().map(|m| m.mount_point.clone()).collect();
+        Ok(Self {
+            mounts,
+            mount_points,
+        })
+    }
+
+    /// The mount a device belongs to. With several (bind mounts), the last
+    /// one, since it is what shadows the others.
+    pub fn find(&self, dev: u64) -> Option<&Mount> {
+        self.mounts.iter().rev().find(|m| m.dev == dev)
+    }
+
+    /// Whether something is mounted on `path`, which has to be canonical.
+    /// Catches bind mounts that keep the parent's `st_dev`.
+    pub fn is_mount_point(&self, path: &Path) -> bool {
+        self.mount_points.contains(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescapes_octal() {
+        assert_eq!(unescape(r"/mnt/a\040b"), "/mnt/a b");
+        assert_eq!(unescape(r"\011tab\012nl\134bs"), "\ttab\nnl\\bs");
+        assert_eq!(unescape(r"/plain"), "/plain");
+    }
+
+    #[test]
+    fn keeps_stray_backslashes() {
+        assert_eq!(unescape(r"a\x"), r"a\x");
+        assert_eq!(unescape(r"a\09"), r"a\09");
+        assert_eq!(unescape(r"end\"), r"end\");
+        assert_eq!(unescape(r"\1"), r"\1");
+    }
+
+    #[test]
+    fn parses_mountinfo_line() {
+        let m = parse_line(
+            r"36 35 98:0 /mnt1 /mnt/parent\040dir rw,noatime master:1 - ext3 /dev/root rw,errors=continue",
+        )
+        .unwrap();
+        assert_eq!(m.dev, libc::makedev(98, 0));
+        assert_eq!(m.mount_point, Path::new("/mnt/parent dir"));
+        assert_eq!(m.fstype, "ext3");
+        assert_eq!(m.source, "/dev/root");
+    }
+
+    #[test]
+    fn without_optional_fields() {
+        let m = parse_line("25 1 0:22 / /tmp rw - tmpfs tmpfs rw,size=8g").unwrap();
+        assert_eq!(m.dev, libc::makedev(0, 22));
+        assert_eq!(m.mount_point, Path::new("/tmp"));
+        assert_eq!((m.fstype.as_str(), m.source.as_str()), ("tmpfs", "tmpfs"));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "",
+            "25 1 0:22 / /tmp rw tmpfs tmpfs rw",
+            "25 1 0-22 / /tmp rw - tmpfs tmpfs rw",
+            "25 1 0:22 / - tmpfs tmpfs rw",
+            "25 1 0:22 / /tmp rw - tmpfs",
+        ] {
+            assert!(parse_line(line).is_none(), "{line:?}");
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::{collections::VecDeque, sync::Mutex, thread};
+
+/// Default worker count: one per available CPU.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Maps `f` over `items` on up to `threads` scoped workers and returns the
+/// results in input order. Every worker starts on its own contiguous slice of
+/// indices and, once that runs dry, steals from the back of the other queues,
+/// so a few slow entries (network mounts, huge `--total-size` trees) don't
+/// leave the remaining workers idle.
+pub fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = threads.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk = items.len().div_ceil(threads);
+    let queues: Vec<Mutex<VecDeque<usize>>> = (0..threads)
+        .map(|t| Mutex::new((t * chunk..((t + 1) * chunk).min(items.len())).collect()))
+        .collect();
+
+    let steal =
+        |me: usize| (1..threads).find_map(|k| queues[(me + k) % threads].lock().ok()?.pop_back());
+
+    let mut results: Vec<(usize, R)> = thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|me| {
+                let (queues, steal, f) = (&queues, &steal, &f);
+                s.spawn(move || {
+                    let mut done = Vec::new();
+                    loop {
+                        let own = queues[me].lock().ok().and_then(|mut q| q.pop_front());
+                        match own.or_else(|| steal(me)) {
+                            Some(i) => done.push((i, f(&items[i]))),
+                            None => break done,
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("worker thread panicked"))
+            .collect()
+    });
+
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A process holding a file: pid and command name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holder {
+    pub pid: u32,
+    pub comm: String,
+}
+
+/// Which processes have what open, from one pass over `/proc`.
+#[derive(Debug, Default)]
+pub struct OpenFiles {
+    /// Open descriptors and mapped files, by `(dev, ino)`.
+    files: HashMap<(u64, u64), Vec<Holder>>,
+    /// Open sockets, by socket inode.
+    sockets: HashMap<u64, Vec<Holder>>,
+    /// Socket inode of every bound unix socket path.
+    unix_paths: HashMap<PathBuf, u64>,
+}
+
+impl OpenFiles {
+    /// Scans `/proc/*/fd` and `/proc/*/maps`. Processes that vanish or that
+    /// we may not inspect are skipped without complaint, as is lss itself.
+    pub fn scan() -> Self {
+        let mut res = Self::default();
+        let Ok(procs) = fs::read_dir("/proc") else {
+            return res;
+        };
+        let me = process::id();
+        for entry in procs.flatten() {
+            let Some(pid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|s| s.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            if pid == me {
+                continue;
+            }
+            let dir = entry.path();
+            let Ok(comm) = fs::read_to_string(dir.join("comm")) else {
+                continue;
+            };
+            let holder = Holder {
+                pid,
+                comm: comm.trim_end().to_string(),
+            };
+            res.scan_fds(&dir, &holder);
+            res.scan_maps(&dir, &holder);
+        }
+        res.unix_paths = get_unix_socket_paths();
+        res
+    }
+
+    fn scan_fds(&mut self, dir: &Path, holder: &Holder) {
+        let Ok(fds) = fs::read_dir(dir.join("fd")) else {
+            return;
+        };
+        for fd in fds.flatten() {
+            let path = fd.path();
+            if let Some(ino) = fs::read_link(&path).ok().and_then(|l| {
+                let l = l.to_str()?.strip_prefix("socket:[")?.strip_suffix(']')?;
+                l.parse().ok()
+            }) {
+                push(self.sockets.entry(ino).or_default(), holder);
+            } else if let Ok(md) = fs::metadata(&path) {
+                push(self.files.entry((md.dev(), md.ino())).or_default(), holder);
+            }
+        }
+    }
+
+    fn scan_maps(&mut self, dir: &Path, holder: &Holder) {
+        let Ok(maps) = fs::read_to_string(dir.join("maps")) else {
+            return;
+        };
+        // address perms offset major:minor inode [path]
+        for line in maps.lines() {
+            let mut cols = line.split_whitespace().skip(3);
+            let (Some(dev), Some(ino)) = (cols.next(), cols.next()) else {
+                continue;
+            };
+            let Some((major, minor)) = dev.split_once(':') else {
+                continue;
+            };
+            let (Ok(major), Ok(minor), Ok(ino)) = (
+                u32::from_str_radix(major, 16),
+                u32::from_str_radix(minor, 16),
+                ino.parse::<u64>(),
+            ) else {
+                continue;
+            };
+            if ino != 0 {
+                let key = (libc::makedev(major, minor), ino);
+                push(self.files.entry(key).or_default(), holder);
+            }
+        }
+    }
+
+    /// Processes holding the entry `(dev, ino)` at `path` (canonical). Unix
+    /// sockets are matched through the socket bound at their path.
+    pub fn holders(&self, dev: u64, ino: u64, path: &Path) -> &[Holder] {
+        let socket = self
+            .unix_paths
+            .get(path)
+            .and_then(|ino| self.sockets.get(ino));
+        socket
+            .or_else(|| self.files.get(&(dev, ino)))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+fn push(holders: &mut Vec<Holder>, holder: &Holder) {
+    if !holders.contains(holder) {
+        holders.push(holder.clone());
+    }
+}
+
+/// Bound unix socket paths from `/proc/net/unix`, mapped to their inode.
+fn get_unix_socket_paths() -> HashMap<PathBuf, u64> {
+    let Ok(unix) = fs::read_to_string("/proc/net/unix") else {
+        return HashMap::new();
+    };
+    // Num RefCount Protocol Flags Type St Inode [Path]
+    unix.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut cols = line.split_whitespace().skip(6);
+            let ino = cols.next()?.parse().ok()?;
+            let path = cols.next().filter(|p| p.starts_with('/'))?;
+            Some((PathBuf::from(path), ino))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn finds_child_holding_file() {
+        let path = std::env::temp_dir().join(format!("lss-procs-{}", process::id()));
+        fs::write(&path, "held\n").unwrap();
+        let md = fs::metadata(&path).unwrap();
+
+        let mut child = Command::new("sleep")
+            .arg("30")
+            .stdin(Stdio::from(fs::File::open(&path).unwrap()))
+            .spawn()
+            .expect("sleep is installed");
+        let holders = OpenFiles::scan()
+            .holders(md.dev(), md.ino(), &fs::canonicalize(&path).unwrap())
+            .to_vec();
+        child.kill().unwrap();
+        child.wait().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // only the pid: the scan can race the exec, before `comm` turns to `sleep`
+        assert!(holders.iter().any(|h| h.pid == child.id()), "{holders:?}");
+    }
+}
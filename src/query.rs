@@ -0,0 +1,652 @@
+use std::{borrow::Cow, fmt, path::Path};
+
+use crate::{
+    FEntry, Time,
+    filter::{self, TypeFilter},
+    flags,
+    git::wildmatch,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Str(String),
+    Op(Op),
+    LParen,
+    RParen,
+    Comma,
+    And,
+    Or,
+    Not,
+    In,
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Glob,
+    NotGlob,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Glob => "~",
+            Op::NotGlob => "!~",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Str,
+    Num,
+    Time,
+    Type,
+    Flags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Name,
+    Ext,
+    Path,
+    Type,
+    Size,
+    Blocks,
+    Nlink,
+    Inode,
+    Mtime,
+    Btime,
+    Owner,
+    Group,
+    Uid,
+    Gid,
+    Mode,
+    Depth,
+    Git,
+    Attrs,
+}
+
+impl Field {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "name" => Self::Name,
+            "ext" | "extension" => Self::Ext,
+            "path" => Self::Path,
+            "type" => Self::Type,
+            "size" => Self::Size,
+            "blocks" => Self::Blocks,
+            "nlink" | "links" => Self::Nlink,
+            "inode" | "ino" => Self::Inode,
+            "mtime" | "modified" => Self::Mtime,
+            "btime" | "created" => Self::Btime,
+            "owner" | "user" => Self::Owner,
+            "group" => Self::Group,
+            "uid" => Self::Uid,
+            "gid" => Self::Gid,
+            "mode" | "perms" => Self::Mode,
+            "depth" => Self::Depth,
+            "git" => Self::Git,
+            "attrs" | "flags" => Self::Attrs,
+            _ => return None,
+        })
+    }
+    fn kind(&self) -> Kind {
+        match self {
+            Self::Size
+            | Self::Blocks
+            | Self::Nlink
+            | Self::Inode
+            | Self::Uid
+            | Self::Gid
+            | Self::Depth => Kind::Num,
+            Self::Mtime | Self::Btime => Kind::Time,
+            Self::Type => Kind::Type,
+            Self::Attrs => Kind::Flags,
+            _ => Kind::Str,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Str(String),
+    Num(u64),
+    /// Durations are turned into `now - d` up front, so times always compare
+    /// in timestamp order: `mtime < 2d` is older than two days.
+    Time(Time),
+    Type(TypeFilter),
+    /// Inode flag bits that all have to be set.
+    Flags(u32),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Field, Op, Value),
+    In(Field, Vec<Value>),
+}
+
+/// Parse failure pointing at the byte offset of the offending token.
+#[derive(Debug)]
+pub struct QueryError {
+    src: String,
+    pos: usize,
+    msg: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let col = self.src[..self.pos.min(self.src.len())].chars().count();
+        writeln!(f, "{} at position {}", self.msg, self.pos)?;
+        writeln!(f, "  {}", self.src)?;
+        write!(f, "  {}^", " ".repeat(col))
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace()
+        && !matches!(
+            c,
+            '(' | ')' | ',' | '=' | '!' | '<' | '>' | '~' | '"' | '\''
+        )
+}
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Tok)>, QueryError> {
+    let err = |pos, msg: &str| QueryError {
+        src: src.to_string(),
+        pos,
+        msg: msg.to_string(),
+    };
+
+    let mut toks = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(pos, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let two = src.get(pos..pos + 2);
+        let (tok, len) = match (c, two) {
+            (_, Some("==")) => (Tok::Op(Op::Eq), 2),
+            (_, Some("!=")) => (Tok::Op(Op::Ne), 2),
+            (_, Some("<=")) => (Tok::Op(Op::Le), 2),
+            (_, Some(">=")) => (Tok::Op(Op::Ge), 2),
+            (_, Some("!~")) => (Tok::Op(Op::NotGlob), 2),
+            (_, Some("&&")) => (Tok::And, 2),
+            (_, Some("||")) => (Tok::Or, 2),
+            ('=', _) => (Tok::Op(Op::Eq), 1),
+            ('<', _) => (Tok::Op(Op::Lt), 1),
+            ('>', _) => (Tok::Op(Op::Gt), 1),
+            ('~', _) => (Tok::Op(Op::Glob), 1),
+            ('!', _) => (Tok::Not, 1),
+            ('(', _) => (Tok::LParen, 1),
+            (')', _) => (Tok::RParen, 1),
+            (',', _) => (Tok::Comma, 1),
+            ('"' | '\'', _) => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, q)) if q == c => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, e)) => s.push(e),
+                            None => return Err(err(pos, "unterminated string")),
+                        },
+                        Some((_, ch)) => s.push(ch),
+                        None => return Err(err(pos, "unterminated string")),
+                    }
+                }
+                toks.push((pos, Tok::Str(s)));
+                continue;
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&(_, ch)) = chars.peek()
+                    && is_word_char(ch)
+                {
+                    s.push(ch);
+                    chars.next();
+                }
+                let tok = match s.to_lowercase().as_str() {
+                    "and" => Tok::And,
+                    "or" => Tok::Or,
+                    "not" => Tok::Not,
+                    "in" => Tok::In,
+                    _ => Tok::Word(s),
+                };
+                toks.push((pos, tok));
+                continue;
+            }
+        };
+        for _ in 0..len {
+            chars.next();
+        }
+        toks.push((pos, tok));
+    }
+    toks.push((src.len(), Tok::End));
+    Ok(toks)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    toks: Vec<(usize, Tok)>,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Tok {
+        &self.toks[self.at].1
+    }
+    fn pos(&self) -> usize {
+        self.toks[self.at].0
+    }
+    fn next(&mut self) -> (usize, Tok) {
+        let tok = self.toks[self.at].clone();
+        if self.at < self.toks.len() - 1 {
+            self.at += 1;
+        }
+        tok
+    }
+    fn err<T>(&self, pos: usize, msg: impl Into<String>) -> Result<T, QueryError> {
+        Err(QueryError {
+            src: self.src.to_string(),
+            pos,
+            msg: msg.into(),
+        })
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.and()?;
+        while *self.peek() == Tok::Or {
+            self.next();
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.not()?;
+        while *self.peek() == Tok::And {
+            self.next();
+            lhs = Expr::And(Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        if *self.peek() == Tok::Not {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+    fn atom(&mut self) -> Result<Expr, QueryError> {
+        let (pos, tok) = self.next();
+        let name = match tok {
+            Tok::LParen => {
+                let expr = self.or()?;
+                let (pos, tok) = self.next();
+                if tok != Tok::RParen {
+                    return self.err(pos, "expected `)`");
+                }
+                return Ok(expr);
+            }
+            Tok::Word(name) => name,
+            Tok::End => return self.err(pos, "expected a condition"),
+            _ => return self.err(pos, "expected a field name"),
+        };
+        let Some(field) = Field::parse(&name.to_lowercase()) else {
+            return self.err(
+                pos,
+                format!(
+                    "unknown field `{name}` (expected one of name, ext, path, type, size, \
+                     blocks, nlink, inode, mtime, btime, owner, group, uid, gid, mode, depth, git, \
+                     attrs)"
+                ),
+            );
+        };
+
+        let negated = *self.peek() == Tok::Not;
+        if negated {
+            self.next();
+            if *self.peek() != Tok::In {
+                return self.err(self.pos(), "expected `in` after `not`");
+            }
+        }
+        let (pos, tok) = self.next();
+        match tok {
+            Tok::Op(op) => {
+                if matches!(op, Op::Glob | Op::NotGlob) && field.kind() != Kind::Str {
+                    return self.err(pos, format!("`{op}` only applies to text fields"));
+                }
+                if matches!(op, Op::Lt | Op::Le | Op::Gt | Op::Ge)
+                    && matches!(field.kind(), Kind::Type | Kind::Flags)
+                {
+                    return self.err(pos, format!("`{op}` cannot be used with `{name}`"));
+                }
+                let value = self.value(field, matches!(op, Op::Glob | Op::NotGlob))?;
+                Ok(Expr::Cmp(field, op, value))
+            }
+            Tok::In => {
+                let (pos, tok) = self.next();
+                if tok != Tok::LParen {
+                    return self.err(pos, "expected `(` after `in`");
+                }
+                let mut values = vec![self.value(field, false)?];
+                loop {
+                    let (pos, tok) = self.next();
+                    match tok {
+                        Tok::Comma => values.push(self.value(field, false)?),
+                        Tok::RParen => break,
+                        _ => return self.err(pos, "expected `,` or `)`"),
+                    }
+                }
+                let expr = Expr::In(field, values);
+                Ok(if negated {
+                    Expr::Not(Box::new(expr))
+                } else {
+                    expr
+                })
+            }
+            _ => self.err(pos, format!("expected an operator after `{name}`")),
+        }
+    }
+    fn value(&mut self, field: Field, glob: bool) -> Result<Value, QueryError> {
+        let (pos, tok) = self.next();
+        let raw = match tok {
+            Tok::Word(s) | Tok::Str(s) => s,
+            _ => return self.err(pos, "expected a value"),
+        };
+        if glob {
+            return Ok(Value::Str(raw));
+        }
+        match field.kind() {
+            Kind::Str => Ok(Value::Str(raw)),
+            Kind::Num => match filter::parse_size(&raw) {
+                Ok(n) => Ok(Value::Num(n)),
+                Err(e) => self.err(pos, e),
+            },
+            Kind::Time => match filter::parse_time(&raw) {
+                Ok(t) => Ok(Value::Time(t)),
+                Err(e) => self.err(pos, e),
+            },
+            Kind::Type => match raw.parse::<TypeFilter>() {
+                Ok(t) => Ok(Value::Type(t)),
+                Err(e) => self.err(pos, e),
+            },
+            Kind::Flags => match flags::parse_letters(&raw) {
+                Ok(f) => Ok(Value::Flags(f)),
+                Err(e) => self.err(pos, e),
+            },
+        }
+    }
+}
+
+enum FieldValue<'a> {
+    Str(Cow<'a, str>),
+    Num(u64),
+    Time(&'a Time),
+    Flags(u32),
+    Missing,
+}
+
+/// A parsed `--where` expression such as `size > 10M and ext in (log, gz)`.
+#[derive(Debug, Clone)]
+pub struct Query {
+    expr: Expr,
+}
+
+impl Query {
+    pub fn parse(src: &str) -> Result<Self, QueryError> {
+        let mut parser = Parser {
+            src,
+            toks: tokenize(src)?,
+            at: 0,
+        };
+        let expr = parser.or()?;
+        if *parser.peek() != Tok::End {
+            return parser.err(parser.pos(), "expected `and`, `or` or end of query");
+        }
+        Ok(Self { expr })
+    }
+
+    fn field<'a>(fe: &'a FEntry, root: &Path, field: Field) -> FieldValue<'a> {
+        match field {
+            Field::Name => FieldValue::Str(Cow::Borrowed(&fe.name)),
+            Field::Ext => FieldValue::Str(Cow::Borrowed(
+                fe.name
+                    .rsplit_once('.')
+                    .filter(|(stem, _)| !stem.is_empty())
+                    .map_or("", |(_, ext)| ext),
+            )),
+            Field::Path => FieldValue::Str(fe.path.to_string_lossy()),
+            Field::Type => FieldValue::Str(Cow::Borrowed("")),
+            Field::Size if fe.has(libc::STATX_SIZE) => FieldValue::Num(fe.size),
+            Field::Blocks if fe.has(libc::STATX_BLOCKS) => FieldValue::Num(fe.nblocks.div_ceil(2)),
+            Field::Nlink if fe.has(libc::STATX_NLINK) => FieldValue::Num(fe.nlink),
+            Field::Inode if fe.has(libc::STATX_INO) => FieldValue::Num(fe.ino),
+            Field::Mtime if fe.has(libc::STATX_MTIME) => FieldValue::Time(&fe.modified),
+            Field::Btime => fe
+                .created
+                .as_ref()
+                .map_or(FieldValue::Missing, FieldValue::Time),
+            Field::Owner => FieldValue::Str(Cow::Borrowed(&fe.owner)),
+            Field::Group => FieldValue::Str(Cow::Borrowed(&fe.group)),
+            Field::Uid if fe.has(libc::STATX_UID) => FieldValue::Num(fe.uid as u64),
+            Field::Gid if fe.has(libc::STATX_GID) => FieldValue::Num(fe.gid as u64),
+            Field::Mode => FieldValue::Str(Cow::Borrowed(&fe.mode)),
+            Field::Depth => FieldValue::Num(
+                fe.path
+                    .strip_prefix(root)
+                    .map_or(1, |p| p.components().count()) as u64,
+            ),
+            Field::Git => FieldValue::Str(Cow::Borrowed(fe.git.as_deref().unwrap_or(""))),
+            Field::Attrs => fe.flags.map_or(FieldValue::Missing, FieldValue::Flags),
+            Field::Size
+            | Field::Blocks
+            | Field::Nlink
+            | Field::Inode
+            | Field::Mtime
+            | Field::Uid
+            | Field::Gid => FieldValue::Missing,
+        }
+    }
+
+    fn compare(&self, fe: &FEntry, root: &Path, field: Field, op: Op, value: &Value) -> bool {
+        use std::cmp::Ordering;
+
+        let ord = match (Self::field(fe, root, field), value) {
+            (_, Value::Type(t)) => {
+                let eq = t.matches(&fe.ftype);
+                return if op == Op::Ne { !eq } else { eq };
+            }
+            (FieldValue::Str(s), Value::Str(v)) => match op {
+                Op::Glob => return wildmatch(v.as_bytes(), s.as_bytes()),
+                Op::NotGlob => return !wildmatch(v.as_bytes(), s.as_bytes()),
+                _ => s.as_ref().cmp(v.as_str()),
+            },
+            (FieldValue::Flags(f), Value::Flags(v)) => {
+                let set = f & v == *v;
+                return if op == Op::Ne { !set } else { set };
+            }
+            (FieldValue::Num(n), Value::Num(v)) => n.cmp(v),
+            (FieldValue::Time(t), Value::Time(v)) => t.secs().cmp(&v.secs()),
+            _ => return false,
+        };
+
+        match op {
+            Op::Eq => ord == Ordering::Equal,
+            Op::Ne => ord != Ordering::Equal,
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+            Op::Gt => ord == Ordering::Greater,
+            Op::Ge => ord != Ordering::Less,
+            Op::Glob | Op::NotGlob => false,
+        }
+    }
+
+    fn eval(&self, expr: &Expr, fe: &FEntry, root: &Path) -> bool {
+        match expr {
+            Expr::And(a, b) => self.eval(a, fe, root) && self.eval(b, fe, root),
+            Expr::Or(a, b) => self.eval(a, fe, root) || self.eval(b, fe, root),
+            Expr::Not(e) => !self.eval(e, fe, root),
+            Expr::Cmp(field, op, value) => self.compare(fe, root, *field, *op, value),
+            Expr::In(field, values) => values
+                .iter()
+                .any(|v| self.compare(fe, root, *field, Op::Eq, v)),
+        }
+    }
+
+    fn uses(&self, field: Field) -> bool {
+        fn walk(expr: &Expr, field: Field) -> bool {
+            match expr {
+                Expr::And(a, b) | Expr::Or(a, b) => walk(a, field) || walk(b, field),
+                Expr::Not(e) => walk(e, field),
+                Expr::Cmp(f, ..) | Expr::In(f, _) => *f == field,
+            }
+        }
+        walk(&self.expr, field)
+    }
+
+    /// Whether any condition looks at inode flags, which cost an extra open
+    /// and ioctl per entry.
+    pub fn uses_flags(&self) -> bool {
+        self.uses(Field::Attrs)
+    }
+
+    /// Whether any condition looks at the git status, which needs the index
+    /// and `HEAD` tree loaded even without `--git`.
+    pub fn uses_git(&self) -> bool {
+        self.uses(Field::Git)
+    }
+
+    /// Evaluates the query for `fe`, with `depth` counted from `root`.
+    pub fn matches(&self, fe: &FEntry, root: &Path) -> bool {
+        self.eval(&self.expr, fe, root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show(expr: &Expr) -> String {
+        let value = |v: &Value| match v {
+            Value::Str(s) => s.clone(),
+            Value::Num(n) => n.to_string(),
+            Value::Time(t) => format!("@{}", t.secs()),
+            Value::Type(t) => format!("{t:?}"),
+            Value::Flags(f) => format!("{f:#x}"),
+        };
+        match expr {
+            Expr::And(a, b) => format!("({} and {})", show(a), show(b)),
+            Expr::Or(a, b) => format!("({} or {})", show(a), show(b)),
+            Expr::Not(e) => format!("not {}", show(e)),
+            Expr::Cmp(f, op, v) => format!("{f:?} {op} {}", value(v)),
+            Expr::In(f, vs) => format!(
+                "{f:?} in [{}]",
+                vs.iter().map(value).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+
+    fn parse(src: &str) -> String {
+        show(&Query::parse(src).unwrap().expr)
+    }
+
+    fn error(src: &str) -> String {
+        Query::parse(src).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("name = a or name = b and size > 10"),
+            "(Name = a or (Name = b and Size > 10))"
+        );
+        assert_eq!(
+            parse("(name = a || name = b) && size > 10"),
+            "((Name = a or Name = b) and Size > 10)"
+        );
+        assert_eq!(
+            parse("not name = a and name = b"),
+            "(not Name = a and Name = b)"
+        );
+        assert_eq!(parse("! ! name = a"), "not not Name = a");
+    }
+
+    #[test]
+    fn in_and_not_in() {
+        assert_eq!(parse("ext in (log, 'gz')"), "Ext in [log, gz]");
+        assert_eq!(
+            parse("ext not in (log) or size >= 1k"),
+            "(not Ext in [log] or Size >= 1024)"
+        );
+    }
+
+    #[test]
+    fn durations_compare_as_timestamps() {
+        let now = Time::from(std::time::SystemTime::now()).secs();
+        let Expr::Cmp(Field::Mtime, Op::Lt, Value::Time(t)) =
+            Query::parse("mtime < 2d").unwrap().expr
+        else {
+            panic!("not a time comparison");
+        };
+        assert!((now - 2 * 86400).abs_diff(t.secs()) <= 1);
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(
+            error("ext not = x"),
+            "expected `in` after `not` at position 8\n  ext not = x\n          ^"
+        );
+        assert_eq!(
+            error("name = a and"),
+            "expected a condition at position 12\n  name = a and\n              ^"
+        );
+        assert_eq!(
+            error("size >> 1"),
+            "expected a value at position 6\n  size >> 1\n        ^"
+        );
+        assert_eq!(
+            error("name = a )"),
+            "expected `and`, `or` or end of query at position 9\n  name = a )\n           ^"
+        );
+        assert_eq!(
+            error("name = 'x"),
+            "unterminated string at position 7\n  name = 'x\n         ^"
+        );
+        assert_eq!(
+            error("size ~ 1"),
+            "`~` only applies to text fields at position 5\n  size ~ 1\n       ^"
+        );
+    }
+
+    #[test]
+    fn error_caret_counts_chars() {
+        // `é` is two bytes but one column
+        let err = error("name = é and nope = 1");
+        let lines: Vec<&str> = err.lines().collect();
+        assert!(lines[0].starts_with("unknown field `nope`"));
+        assert!(lines[0].ends_with("at position 14"));
+        assert_eq!(lines[2], format!("  {}^", " ".repeat(13)));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    ffi::CString,
+    io, mem,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use crate::Time;
+
+/// Set once `statx` turns out to be missing (`ENOSYS` on pre-4.11 kernels,
+/// `EPERM` from seccomp sandboxes that filter it) so the rest of the run goes
+/// straight to `fstatat`.
+static NO_STATX: AtomicBool = AtomicBool::new(false);
+
+/// Metadata of a single entry, as returned by `statx(2)`. Only the fields
+/// whose `STATX_*` bit is set in `mask` were supplied by the filesystem.
+#[derive(Debug)]
+pub struct Stat {
+    pub mask: u32,
+    pub mode: u32,
+    pub nlink: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub ino: u64,
+    pub dev: u64,
+    pub size: u64,
+    pub blocks: u64,
+    /// Preferred I/O block size, i.e. the allocation granularity.
+    pub blksize: u64,
+    pub mtime: Time,
+    pub btime: Option<Time>,
+    pub attributes: u64,
+    pub attributes_mask: u64,
+}
+
+fn get_time(sec: i64, nsec: u32) -> Time {
+    let since_epoch = Duration::new(sec.max(0) as u64, nsec);
+    Time::from(SystemTime::UNIX_EPOCH + since_epoch)
+}
+
+impl Stat {
+    /// `lstat`-like lookup of `path` asking the filesystem for the `STATX_*`
+    /// fields in `mask` only.
+    pub fn lstat(path: &Path, mask: u32) -> io::Result<Self> {
+        let cpath = CString::new(path.as_os_str().as_bytes())?;
+        if !NO_STATX.load(Ordering::Relaxed) {
+            match Self::statx(&cpath, mask) {
+                Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                    NO_STATX.store(true, Ordering::Relaxed);
+                }
+                Err(e) if e.raw_os_error() == Some(libc::EPERM) => {
+                    // a filtered syscall, unless `fstatat` is refused as well
+                    let res = Self::fstatat(&cpath);
+                    if res.is_ok() {
+                        NO_STATX.store(true, Ordering::Relaxed);
+                    }
+                    return res.map_err(|_| e);
+                }
+                res => return res,
+            }
+        }
+        Self::fstatat(&cpath)
+    }
+
+    fn statx(path: &CString, mask: u32) -> io::Result<Self> {
+        let mut stx: libc::statx = unsafe { mem::zeroed() };
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_statx,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW | libc::AT_NO_AUTOMOUNT,
+                mask,
+                &mut stx,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let got = stx.stx_mask;
+        Ok(Self {
+            mask: got,
+            mode: stx.stx_mode as u32,
+            nlink: stx.stx_nlink as u64,
+            uid: stx.stx_uid,
+            gid: stx.stx_gid,
+            ino: stx.stx_ino,
+            dev: libc::makedev(stx.stx_dev_major, stx.stx_dev_minor),
+            size: stx.stx_size,
+            blocks: stx.stx_blocks,
+            blksize: stx.stx_blksize as u64,
+            mtime: get_time(stx.stx_mtime.tv_sec, stx.stx_mtime.tv_nsec),
+            btime: (got & libc::STATX_BTIME != 0)
+                .then(|| get_time(stx.stx_btime.tv_sec, stx.stx_btime.tv_nsec)),
+            attributes: stx.stx_attributes,
+            attributes_mask: stx.stx_attributes_mask,
+        })
+    }
+
+    fn fstatat(path: &CString) -> io::Result<Self> {
+        let mut st: libc::stat = unsafe { mem::zeroed() };
+        let ret = unsafe {
+            libc::fstatat(
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                &mut st,
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            mask: libc::STATX_BASIC_STATS,
+            mode: st.st_mode,
+            nlink: st.st_nlink as u64,
+            uid: st.st_uid,
+            gid: st.st_gid,
+            ino: st.st_ino,
+            dev: st.st_dev,
+            size: st.st_size as u64,
+            blocks: st.st_blocks as u64,
+            blksize: st.st_blksize as u64,
+            mtime: get_time(st.st_mtime, st.st_mtime_nsec as u32),
+            btime: None,
+            attributes: 0,
+            attributes_mask: 0,
+        })
+    }
+
+    pub fn has(&self, field: u32) -> bool {
+        self.mask & field != 0
+    }
+
+    fn file_type(&self) -> u32 {
+        self.mode & libc::S_IFMT
+    }
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == libc::S_IFDIR
+    }
+    pub fn is_file(&self) -> bool {
+        self.file_type() == libc::S_IFREG
+    }
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == libc::S_IFLNK
+    }
+    pub fn is_fifo(&self) -> bool {
+        self.file_type() == libc::S_IFIFO
+    }
+    pub fn is_socket(&self) -> bool {
+        self.file_type() == libc::S_IFSOCK
+    }
+    pub fn is_block_device(&self) -> bool {
+        self.file_type() == libc::S_IFBLK
+    }
+    pub fn is_char_device(&self) -> bool {
+        self.file_type() == libc::S_IFCHR
+    }
+
+    /// `stx_attributes` as a fixed string: a letter (`chattr` naming) for each
+    /// set attribute, `.` when it is clear and `-` when the filesystem does
+    /// not report it at all.
+    pub fn attributes_str(&self) -> String {
+        [
+            (libc::STATX_ATTR_IMMUTABLE, 'i'),
+            (libc::STATX_ATTR_APPEND, 'a'),
+            (libc::STATX_ATTR_COMPRESSED, 'c'),
+            (libc::STATX_ATTR_ENCRYPTED, 'E'),
+            (libc::STATX_ATTR_VERITY, 'V'),
+            (libc::STATX_ATTR_DAX, 'x'),
+        ]
+        .iter()
+        .map(|&(attr, c)| {
+            let attr = attr as u64;
+            if self.attributes_mask & attr == 0 {
+                '-'
+            } else if self.attributes & attr != 0 {
+                c
+            } else {
+                '.'
+            }
+        })
+        .collect()
+    }
+}
@@ -0,0 +1,86 @@
+use std::{str::FromStr, time::SystemTime};
+
+use colored::{ColoredString, Colorize};
+
+use crate::Time;
+
+/// Long-format columns the opt-in `--theme` can colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemePart {
+    Perms,
+    Size,
+    Age,
+    Owner,
+    All,
+}
+impl FromStr for ThemePart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perms" | "mode" => Ok(Self::Perms),
+            "size" => Ok(Self::Size),
+            "age" | "time" => Ok(Self::Age),
+            "owner" => Ok(Self::Owner),
+            "all" => Ok(Self::All),
+            _ => Err(format!(
+                "unknown theme part `{s}`, expected one of perms,size,age,owner,all"
+            )),
+        }
+    }
+}
+
+/// The mode string with each permission bit in its own colour and the
+/// setuid, setgid and sticky bits highlighted.
+pub fn style_mode(mode: &str) -> String {
+    mode.chars()
+        .enumerate()
+        .map(|(i, c)| match (i, c) {
+            (0, 'd') => c.to_string().blue().bold(),
+            (0, 'l') => c.to_string().cyan(),
+            (0, 'p' | 's' | 'b' | 'c') => c.to_string().yellow(),
+            (_, 'r') => c.to_string().yellow(),
+            (_, 'w') => c.to_string().red(),
+            (_, 'x') => c.to_string().green(),
+            (_, 's' | 'S' | 't' | 'T') => c.to_string().black().on_magenta(),
+            (_, '-') => c.to_string().dimmed(),
+            _ => c.to_string().normal(),
+        })
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// `text` coloured by the order of magnitude of `size`.
+pub fn style_size(text: &str, size: u64) -> ColoredString {
+    match size {
+        0..1024 => text.bright_green(),
+        1024..0x10_0000 => text.green(),
+        0x10_0000..0x4000_0000 => text.yellow(),
+        0x4000_0000..0x100_0000_0000 => text.red(),
+        _ => text.bright_red().bold(),
+    }
+}
+
+/// `text` coloured by how long ago `time` was: the last hour, day, week,
+/// year, or earlier.
+pub fn style_age(text: &str, time: &Time) -> ColoredString {
+    let now = Time::from(SystemTime::now()).secs();
+    match now.saturating_sub(time.secs()) {
+        0..3600 => text.bright_blue().bold(),
+        3600..86400 => text.bright_blue(),
+        86400..604800 => text.blue(),
+        604800..31536000 => text.normal(),
+        _ => text.dimmed(),
+    }
+}
+
+/// `text` highlighted when `uid` is the invoking user or root.
+pub fn style_owner(text: &str, uid: u32) -> ColoredString {
+    if uid == unsafe { libc::geteuid() } {
+        text.yellow().bold()
+    } else if uid == 0 {
+        text.red()
+    } else {
+        text.normal()
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    ffi::{CStr, CString},
+    io,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    ptr,
+};
+
+/// One extended attribute. `value` is only read when it is going to be shown.
+#[derive(Debug)]
+pub struct Xattr {
+    pub name: String,
+    pub value: Option<Vec<u8>>,
+}
+
+impl Xattr {
+    /// The value itself when it is printable text, its length otherwise.
+    pub fn describe(&self) -> String {
+        let Some(value) = &self.value else {
+            return String::new();
+        };
+        // C strings stored by tools like setfattr usually keep their NUL
+        let text = value.strip_suffix(b"\0").unwrap_or(value);
+        match std::str::from_utf8(text) {
+            Ok(s) if !s.is_empty() && !s.chars().any(char::is_control) => format!("{s:?}"),
+            _ => format!("{} bytes", value.len()),
+        }
+    }
+}
+
+/// Calls an `l*xattr` style function twice, first with an empty buffer to
+/// learn the size, and retries while the attribute keeps growing under us.
+fn read_sized(mut call: impl FnMut(*mut libc::c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let len = call(ptr::null_mut(), 0);
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; len as usize];
+        let got = call(buf.as_mut_ptr().cast(), buf.len());
+        if got >= 0 {
+            buf.truncate(got as usize);
+            return Ok(buf);
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+/// Raw value of the attribute `name` of `path`, without following symlinks.
+pub fn get(path: &Path, name: &str) -> io::Result<Vec<u8>> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    let cname = CString::new(name)?;
+    read_sized(|buf, len| unsafe { libc::lgetxattr(cpath.as_ptr(), cname.as_ptr(), buf, len) })
+}
+
+/// Names of the attributes of `path`, without following symlinks. A
+/// filesystem without xattr support simply has none.
+pub fn list(path: &Path) -> io::Result<Vec<String>> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    let names =
+        match read_sized(|buf, len| unsafe { libc::llistxattr(cpath.as_ptr(), buf.cast(), len) }) {
+            Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(Vec::new()),
+            res => res?,
+        };
+    Ok(names
+        .split_inclusive(|&b| b == 0)
+        .filter_map(|n| CStr::from_bytes_with_nul(n).ok())
+        .map(|n| n.to_string_lossy().into_owned())
+        .collect())
+}
+
+/// Every attribute of `path`, with values when `values` is set. Attributes
+/// removed between listing and reading them are skipped.
+pub fn read_all(path: &Path, values: bool) -> io::Result<Vec<Xattr>> {
+    let mut attrs = Vec::new();
+    for name in list(path)? {
+        let value = if values {
+            match get(path, &name) {
+                Ok(value) => Some(value),
+                Err(e) if e.raw_os_error() == Some(libc::ENODATA) => continue,
+                Err(e) => return Err(e),
+            }
+        } else {
+            None
+        };
+        attrs.push(Xattr { name, value });
+    }
+    Ok(attrs)
+}